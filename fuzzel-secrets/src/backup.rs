@@ -0,0 +1,175 @@
+use crate::export::{self, Export};
+use crate::secret::Secret;
+use crate::timestamp;
+use crate::xdg;
+use anyhow::{Context, Result};
+use std::fs::{self, DirBuilder};
+use std::io::ErrorKind;
+use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+
+/// Directory holding backups of overwritten secrets
+pub fn dir() -> Option<PathBuf> {
+    xdg::state_dir().map(|dir| dir.join("backups"))
+}
+
+/// Back up the contents of a secret before it is overwritten
+///
+/// The backup is written in the export format to a file named after the
+/// current time and the label, readable only by the current user. Afterwards
+/// only the `keep` newest backups are kept; `0` keeps all of them.
+pub fn write(label: &str, data: &Secret, keep: usize) -> Result<PathBuf> {
+    let dir = dir().context("Failed to determine backup location")?;
+    write_in(&dir, label, data, keep, timestamp::now())
+}
+
+fn write_in(dir: &Path, label: &str, data: &Secret, keep: usize, now: i64) -> Result<PathBuf> {
+    DirBuilder::new()
+        .recursive(true)
+        .mode(0o700)
+        .create(dir)
+        .with_context(|| format!("Failed to create {}", dir.display()))?;
+    // Created with the default permissions by earlier versions
+    fs::set_permissions(dir, fs::Permissions::from_mode(0o700))
+        .with_context(|| format!("Failed to restrict access to {}", dir.display()))?;
+
+    let existing = list(dir)?;
+    let taken = |prefix: &str| {
+        existing.iter().any(|path| {
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| name.starts_with(prefix))
+        })
+    };
+    let sequence = (0..)
+        .find(|&sequence| !taken(&time_prefix(now, sequence)))
+        .unwrap_or_default();
+    let path = dir.join(file_name(label, now, sequence));
+    let backup = Export::from([(label.to_string(), data.clone())]);
+    export::write(&path, &backup)?;
+
+    if keep > 0 {
+        prune(dir, keep)?;
+    }
+
+    Ok(path)
+}
+
+/// Backup files, oldest first
+pub fn list(dir: &Path) -> Result<Vec<PathBuf>> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err).with_context(|| format!("Failed to read {}", dir.display())),
+    };
+
+    let mut paths = Vec::new();
+    for entry in entries {
+        let path = entry
+            .with_context(|| format!("Failed to read {}", dir.display()))?
+            .path();
+        if path.extension().is_some_and(|ext| ext == "json") {
+            paths.push(path);
+        }
+    }
+    // File names start with a fixed-width UTC time and sequence number, so
+    // they sort chronologically
+    paths.sort();
+    Ok(paths)
+}
+
+/// Remove all but the `keep` newest backups
+fn prune(dir: &Path, keep: usize) -> Result<()> {
+    let paths = list(dir)?;
+    let excess = paths.len().saturating_sub(keep);
+    for path in &paths[..excess] {
+        fs::remove_file(path).with_context(|| format!("Failed to remove {}", path.display()))?;
+    }
+    Ok(())
+}
+
+/// Name a backup like `20250131T120000Z-work_aws.json`
+///
+/// Later backups written within the same second are numbered by `sequence`,
+/// like `20250131T120000Z.001-work_aws.json`, rather than overwriting
+/// earlier ones. Characters other than ASCII letters, digits, `-` and `.` in
+/// the label are replaced with `_`, so namespaced labels don't create
+/// subdirectories.
+fn file_name(label: &str, now: i64, sequence: u32) -> String {
+    let label: String = label
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || matches!(c, '-' | '.') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    format!("{}{}.json", time_prefix(now, sequence), label)
+}
+
+/// The start of the names of backups written at `now`, up to the label
+fn time_prefix(now: i64, sequence: u32) -> String {
+    let time: String = timestamp::format_rfc3339(now)
+        .chars()
+        .filter(|c| !matches!(c, '-' | ':'))
+        .collect();
+    match sequence {
+        0 => format!("{}-", time),
+        _ => format!("{}.{:03}-", time, sequence),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_file_name() {
+        assert_eq!(
+            file_name("work/aws", 1709209815, 0),
+            "20240229T123015Z-work_aws.json"
+        );
+        assert_eq!(
+            file_name("work/aws", 1709209815, 2),
+            "20240229T123015Z.002-work_aws.json"
+        );
+    }
+
+    #[test]
+    fn test_write_and_prune() {
+        let dir =
+            std::env::temp_dir().join(format!("fuzzel-secrets-backup-{}", std::process::id()));
+        let mut data = Secret::new();
+        data.insert("password".to_string(), "hunter2".to_string());
+
+        for now in [30, 10, 20, 20] {
+            write_in(&dir, "GitHub", &data, 3, now).unwrap();
+        }
+
+        let paths = list(&dir).unwrap();
+        let names: Vec<_> = paths
+            .iter()
+            .map(|p| p.file_name().unwrap().to_str().unwrap())
+            .collect();
+        assert_eq!(
+            names,
+            vec![
+                "19700101T000020Z-GitHub.json",
+                "19700101T000020Z.001-GitHub.json",
+                "19700101T000030Z-GitHub.json"
+            ]
+        );
+
+        let mode = fs::metadata(&dir).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o700);
+
+        let mode = fs::metadata(&paths[0]).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        let backup = export::from_json(&fs::read_to_string(&paths[0]).unwrap()).unwrap();
+        assert_eq!(backup["GitHub"], data);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
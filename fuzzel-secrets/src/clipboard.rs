@@ -0,0 +1,187 @@
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use serde::Deserialize;
+use std::io::{self, ErrorKind, Write};
+use std::process::{Command, Stdio};
+
+/// Supported clipboard tools
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum ClipTool {
+    /// wl-copy from wl-clipboard (Wayland)
+    #[default]
+    WlCopy,
+    /// xclip (X11/XWayland)
+    Xclip,
+    /// xsel (X11/XWayland)
+    Xsel,
+}
+
+impl ClipTool {
+    fn program(&self) -> &'static str {
+        match self {
+            ClipTool::WlCopy => "wl-copy",
+            ClipTool::Xclip => "xclip",
+            ClipTool::Xsel => "xsel",
+        }
+    }
+
+    /// Arguments for copying stdin to the clipboard
+    fn copy_args(&self) -> &'static [&'static str] {
+        match self {
+            ClipTool::WlCopy => &[],
+            ClipTool::Xclip => &["-selection", "clipboard"],
+            ClipTool::Xsel => &["--clipboard", "--input"],
+        }
+    }
+
+    /// Arguments for clearing the clipboard, and whether an empty stdin must be provided
+    fn clear_args(&self) -> (&'static [&'static str], bool) {
+        match self {
+            ClipTool::WlCopy => (&["--clear"], false),
+            ClipTool::Xclip => (&["-selection", "clipboard"], true),
+            ClipTool::Xsel => (&["--clipboard", "--clear"], false),
+        }
+    }
+
+    /// Program and arguments printing the clipboard contents exactly
+    fn paste_command(&self) -> (&'static str, &'static [&'static str]) {
+        match self {
+            ClipTool::WlCopy => ("wl-paste", &["--no-newline"]),
+            ClipTool::Xclip => ("xclip", &["-selection", "clipboard", "-out"]),
+            ClipTool::Xsel => ("xsel", &["--clipboard", "--output"]),
+        }
+    }
+
+    fn run(&self, args: &[&str], input: Option<&[u8]>) -> Result<()> {
+        let mut cmd = Command::new(self.program());
+        cmd.args(args);
+        cmd.stdin(if input.is_some() {
+            Stdio::piped()
+        } else {
+            Stdio::null()
+        });
+
+        let mut child = cmd
+            .spawn()
+            .map_err(|err| spawn_error(self.program(), err))?;
+
+        if let (Some(mut stdin), Some(input)) = (child.stdin.take(), input) {
+            stdin
+                .write_all(input)
+                .with_context(|| format!("Failed to write to {} stdin", self.program()))?;
+        }
+
+        let status = child
+            .wait()
+            .with_context(|| format!("Failed to wait for {}", self.program()))?;
+
+        if !status.success() {
+            anyhow::bail!("{} command failed", self.program());
+        }
+
+        Ok(())
+    }
+}
+
+fn spawn_error(program: &str, err: io::Error) -> anyhow::Error {
+    if err.kind() == ErrorKind::NotFound {
+        anyhow::anyhow!(
+            "'{}' not found in PATH; install it or choose another clipboard tool",
+            program
+        )
+    } else {
+        anyhow::Error::new(err).context(format!("Failed to execute {}", program))
+    }
+}
+
+/// Copy a value to the clipboard
+///
+/// With `trim` a single trailing line break, such as the one ending a value
+/// entered in a text field, is left out, like when typing. Other trailing
+/// whitespace is copied as stored. Values that must be reproduced byte for
+/// byte, like keys ending in a line break, are copied without trimming.
+pub fn copy(tool: ClipTool, value: &str, trim: bool) -> Result<()> {
+    tool.run(tool.copy_args(), Some(copy_input(value, trim)))
+}
+
+/// The bytes written to the clipboard tool for a value
+fn copy_input(value: &str, trim: bool) -> &[u8] {
+    if trim {
+        trim_line_break(value).as_bytes()
+    } else {
+        value.as_bytes()
+    }
+}
+
+/// Clear the clipboard
+pub fn clear(tool: ClipTool) -> Result<()> {
+    let (args, empty_input) = tool.clear_args();
+    tool.run(args, empty_input.then_some(&[][..]))
+}
+
+/// The text on the clipboard
+///
+/// With `trim_newline` a single trailing line break is removed, which is
+/// usually copied along with a line of text rather than being part of it.
+pub fn paste(tool: ClipTool, trim_newline: bool) -> Result<String> {
+    let (program, args) = tool.paste_command();
+    let output = Command::new(program)
+        .args(args)
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|err| spawn_error(program, err))?;
+
+    if !output.status.success() {
+        // Only the tool's own message, e.g. that nothing is copied, never the contents
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        match stderr.trim() {
+            "" => anyhow::bail!("{} command failed", program),
+            message => anyhow::bail!("{} command failed: {}", program, message),
+        }
+    }
+
+    let text = String::from_utf8(output.stdout).context("Clipboard doesn't hold text")?;
+    let text = if trim_newline {
+        trim_line_break(&text).to_string()
+    } else {
+        text
+    };
+    if text.is_empty() {
+        anyhow::bail!("Clipboard is empty");
+    }
+    Ok(text)
+}
+
+/// Remove one trailing `\n` or `\r\n`
+fn trim_line_break(text: &str) -> &str {
+    match text.strip_suffix('\n') {
+        Some(text) => text.strip_suffix('\r').unwrap_or(text),
+        None => text,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_copy_input() {
+        assert_eq!(copy_input("hunter2\n", true), b"hunter2");
+        assert_eq!(copy_input("hunter2\r\n", true), b"hunter2");
+        assert_eq!(copy_input("hunter2\n\n", true), b"hunter2\n");
+        assert_eq!(copy_input("abc ", true), b"abc ");
+        assert_eq!(copy_input("abc \t", true), b"abc \t");
+        assert_eq!(copy_input("hunter2\n", false), b"hunter2\n");
+        assert_eq!(copy_input("  hunter2", true), b"  hunter2");
+        assert_eq!(copy_input("hunter2", false), b"hunter2");
+    }
+
+    #[test]
+    fn test_trim_line_break() {
+        assert_eq!(trim_line_break("token\n"), "token");
+        assert_eq!(trim_line_break("token\r\n"), "token");
+        assert_eq!(trim_line_break("two\nlines\n\n"), "two\nlines\n");
+        assert_eq!(trim_line_break("token "), "token ");
+    }
+}
@@ -0,0 +1,192 @@
+use crate::clipboard::ClipTool;
+use crate::generator::PasswordPolicy;
+use crate::resolver;
+use crate::typing::TypeMode;
+use crate::xdg;
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+
+/// Program used to enter sensitive values
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum PasswordSource {
+    /// fuzzel in password mode
+    #[default]
+    Fuzzel,
+    /// pinentry, falling back to fuzzel if it isn't installed
+    Pinentry,
+}
+
+/// What cancelling a prompt while editing the fields of a secret does
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum OnCancel {
+    /// Abandon the current field and return to the field menu
+    #[default]
+    Back,
+    /// Abort storing the secret, discarding every change
+    Discard,
+}
+
+/// User configuration, read from `$XDG_CONFIG_HOME/fuzzel-secrets/config.json`
+///
+/// Every setting is optional; command line flags take precedence over the file.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// Tool used to copy values to the clipboard
+    pub clip_tool: ClipTool,
+    /// Seconds before a copied sensitive value is cleared from the clipboard
+    pub clipboard_clear_seconds: u64,
+    /// Leave a single trailing line break out of values copied to the clipboard
+    pub clipboard_trim: bool,
+    /// Shell command printing the contents of a QR code on screen
+    pub qr_scanner_command: String,
+    /// Seconds revealed values stay on screen with `show --reveal`
+    pub reveal_timeout_seconds: u64,
+    /// Mask every field value entered during store, not just sensitive ones
+    pub mask_all_inputs: bool,
+    /// Briefly show a strength estimate after entering a sensitive value
+    pub show_strength: bool,
+    /// Unlock the collection automatically when it is locked
+    pub auto_unlock: bool,
+    /// Show desktop notifications after storing or retrieving a secret
+    pub notify: bool,
+    /// Offer the field last retrieved from a secret first
+    pub remember_fields: bool,
+    /// Program used to enter sensitive values
+    pub password_source: PasswordSource,
+    /// Requirements sensitive values must meet or be confirmed
+    pub password_policy: PasswordPolicy,
+    /// Warn when retrieving a secret expiring within this many days
+    pub expiry_warning_days: u64,
+    /// Non-sensitive fields also stored as unencrypted, searchable attributes
+    pub searchable_fields: Vec<String>,
+    /// Key patterns of non-sensitive fields whose values are only partially shown
+    pub partial_mask_fields: Vec<String>,
+    /// Keys listed first, in this order, wherever fields are listed
+    pub field_order: Vec<String>,
+    /// Group labels by their `/`-separated namespace in the retrieve picker
+    pub group_labels: bool,
+    /// fuzzel configuration file used for all prompts instead of the default one
+    pub fuzzel_config: Option<PathBuf>,
+    /// Let fuzzel record selections in its usage cache
+    pub fuzzel_cache: bool,
+    /// Length of values generated by `rotate`
+    pub generated_length: usize,
+    /// Hours the previous value of a rotated field is kept for `rotate --undo`
+    pub rotation_retention_hours: u64,
+    /// Back up a secret's previous contents before `store` overwrites it
+    pub backup: bool,
+    /// Number of backups kept, `0` keeps all of them
+    pub max_backups: usize,
+    /// Secret Service schema of the stored items instead of `org.fuzzel.secrets`
+    pub schema: Option<String>,
+    /// Command typing values instead of wtype, with a `{value}` or `{stdin}` placeholder
+    pub type_command: Option<Vec<String>>,
+    /// List only field keys in the retrieve field picker, not their values
+    pub mask_in_picker: bool,
+    /// What cancelling a field prompt during `store` does
+    pub on_cancel: OnCancel,
+    /// Lowercase field keys typed during `store` and replace common aliases
+    pub normalize_keys: bool,
+    /// Additional aliases of field keys for `normalize_keys`
+    pub key_aliases: HashMap<String, String>,
+    /// Suggest field keys used by other secrets during `store`
+    pub key_suggestions: bool,
+    /// Skip key suggestions for collections with more secrets, `0` for no limit
+    pub key_suggestions_max_secrets: usize,
+    /// Sort the fields in the `store` menu instead of keeping the order they were added in
+    pub sort_fields: bool,
+    /// Short names for collection labels, for `--collection`
+    pub collection_aliases: HashMap<String, String>,
+    /// How wtype types values, unless `type_command` is set
+    pub type_mode: TypeMode,
+    /// References to other secrets followed at most to get a single value
+    pub max_reference_depth: usize,
+    /// Remove a trailing line break from values taken from the clipboard during `store`
+    pub paste_trim_newline: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            clip_tool: ClipTool::default(),
+            clipboard_clear_seconds: 30,
+            clipboard_trim: true,
+            qr_scanner_command: r#"grim -g "$(slurp)" - | zbarimg --quiet --raw -"#.to_string(),
+            reveal_timeout_seconds: 10,
+            mask_all_inputs: false,
+            show_strength: false,
+            auto_unlock: true,
+            notify: false,
+            remember_fields: true,
+            password_source: PasswordSource::default(),
+            password_policy: PasswordPolicy::default(),
+            expiry_warning_days: 7,
+            searchable_fields: Vec::new(),
+            partial_mask_fields: Vec::new(),
+            field_order: Vec::new(),
+            group_labels: false,
+            fuzzel_config: None,
+            fuzzel_cache: false,
+            generated_length: 20,
+            rotation_retention_hours: 24,
+            backup: false,
+            max_backups: 50,
+            schema: None,
+            type_command: None,
+            mask_in_picker: true,
+            on_cancel: OnCancel::default(),
+            normalize_keys: false,
+            key_aliases: HashMap::new(),
+            key_suggestions: true,
+            key_suggestions_max_secrets: 200,
+            sort_fields: true,
+            collection_aliases: HashMap::new(),
+            type_mode: TypeMode::default(),
+            max_reference_depth: resolver::DEFAULT_MAX_DEPTH,
+            paste_trim_newline: true,
+        }
+    }
+}
+
+impl Config {
+    /// Location of the configuration file
+    pub fn path() -> Option<PathBuf> {
+        xdg::config_dir().map(|dir| dir.join("config.json"))
+    }
+
+    /// Load the configuration file, using defaults if it doesn't exist
+    pub fn load() -> Result<Config> {
+        let Some(path) = Self::path() else {
+            return Ok(Config::default());
+        };
+
+        match fs::read_to_string(&path) {
+            Ok(contents) => serde_json::from_str(&contents)
+                .with_context(|| format!("Failed to parse config file {}", path.display())),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(Config::default()),
+            Err(err) => {
+                Err(err).with_context(|| format!("Failed to read config file {}", path.display()))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_partial_config_uses_defaults() {
+        let config: Config = serde_json::from_str(r#"{"clip_tool": "xsel"}"#).unwrap();
+        assert_eq!(config.clip_tool, ClipTool::Xsel);
+        assert_eq!(config.clipboard_clear_seconds, 30);
+    }
+}
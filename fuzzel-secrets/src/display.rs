@@ -0,0 +1,33 @@
+use std::env;
+use std::fmt;
+
+/// Error returned when a prompt is needed but there is no graphical session
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NoDisplay;
+
+impl fmt::Display for NoDisplay {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "no display available; this command requires a graphical session"
+        )
+    }
+}
+
+impl std::error::Error for NoDisplay {}
+
+/// Check for a Wayland or X11 display that fuzzel and unlock prompts can use
+pub fn available() -> bool {
+    ["WAYLAND_DISPLAY", "DISPLAY"]
+        .iter()
+        .any(|var| env::var_os(var).is_some_and(|value| !value.is_empty()))
+}
+
+/// Fail early instead of hanging on a prompt that can't be shown, e.g. over ssh
+pub fn require() -> Result<(), NoDisplay> {
+    if available() {
+        Ok(())
+    } else {
+        Err(NoDisplay)
+    }
+}
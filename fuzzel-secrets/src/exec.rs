@@ -0,0 +1,94 @@
+use crate::secret::Secret;
+use anyhow::{bail, Result};
+use std::collections::HashMap;
+
+/// Environment variable name for a field key
+///
+/// ASCII letters and digits are uppercased, every other character becomes `_`,
+/// and names starting with a digit are prefixed with `_`. For example
+/// `api-token` becomes `API_TOKEN` and `2fa` becomes `_2FA`.
+pub fn env_var_name(key: &str) -> String {
+    let name: String = key
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_uppercase()
+            } else {
+                '_'
+            }
+        })
+        .collect();
+
+    if name.starts_with(|c: char| c.is_ascii_digit()) {
+        format!("_{}", name)
+    } else {
+        name
+    }
+}
+
+/// Environment variables for all fields of a secret
+///
+/// Fails instead of silently overwriting when two keys map to the same name.
+pub fn env_vars(data: &Secret) -> Result<Vec<(String, String)>> {
+    let mut sources: HashMap<String, String> = HashMap::new();
+    let mut vars = Vec::new();
+
+    for field in data.iter() {
+        let name = env_var_name(&field.key);
+        if name.is_empty() {
+            bail!(
+                "Field '{}' can't be used as an environment variable",
+                field.key
+            );
+        }
+        if let Some(other) = sources.insert(name.clone(), field.key.clone()) {
+            bail!(
+                "Fields '{}' and '{}' both map to environment variable {}",
+                other,
+                field.key,
+                name
+            );
+        }
+        vars.push((name, field.value));
+    }
+
+    vars.sort();
+    Ok(vars)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_env_var_name() {
+        assert_eq!(env_var_name("password"), "PASSWORD");
+        assert_eq!(env_var_name("api-token"), "API_TOKEN");
+        assert_eq!(env_var_name("2fa"), "_2FA");
+        assert_eq!(env_var_name("brugernavn ø"), "BRUGERNAVN__");
+    }
+
+    #[test]
+    fn test_env_vars_rejects_collisions() {
+        let mut data = Secret::new();
+        data.insert("api-token".to_string(), "a".to_string());
+        data.insert("api_token".to_string(), "b".to_string());
+        assert!(env_vars(&data).is_err());
+    }
+
+    #[test]
+    fn test_env_vars() {
+        let mut data = Secret::new();
+        data.insert("username".to_string(), "john".to_string());
+        data.insert("password".to_string(), "secret".to_string());
+
+        let vars = env_vars(&data).unwrap();
+        assert_eq!(
+            vars,
+            vec![
+                ("PASSWORD".to_string(), "secret".to_string()),
+                ("USERNAME".to_string(), "john".to_string()),
+            ]
+        );
+    }
+}
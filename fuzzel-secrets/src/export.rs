@@ -0,0 +1,271 @@
+use crate::formats;
+use crate::secret::Secret;
+use anyhow::{bail, Context, Result};
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::fs::{self, DirBuilder, OpenOptions};
+use std::io::Write;
+use std::os::unix::fs::{DirBuilderExt, OpenOptionsExt};
+use std::path::{Path, PathBuf};
+
+/// Exported secrets keyed by label, sorted for stable output
+pub type Export = BTreeMap<String, Secret>;
+
+/// Serialize secrets to the JSON export format
+pub fn to_json(export: &Export) -> Result<String> {
+    serde_json::to_string_pretty(export).context("Failed to serialize export")
+}
+
+/// Parse secrets from the JSON export format
+pub fn from_json(json: &str) -> Result<Export> {
+    serde_json::from_str(json).context("Failed to parse export")
+}
+
+/// Check that an export holds at least one secret and that every secret has a
+/// label and fields
+pub fn validate(export: &Export) -> Result<()> {
+    if export.is_empty() {
+        bail!("No secrets found");
+    }
+    for (label, secret) in export {
+        if label.trim().is_empty() {
+            bail!("A secret has an empty label");
+        }
+        if secret.is_empty() {
+            bail!("Secret '{}' has no fields", label);
+        }
+    }
+    Ok(())
+}
+
+/// Write an export to a file only readable by the current user
+pub fn write(path: &Path, export: &Export) -> Result<()> {
+    write_private(path, &to_json(export)?)
+}
+
+/// Write a file only readable by the current user
+///
+/// The contents are written to a new file in the same directory, which then
+/// replaces `path`. An existing file is never left half written, nor keeps
+/// permissions letting others read the new contents.
+fn write_private(path: &Path, contents: &str) -> Result<()> {
+    let name = path
+        .file_name()
+        .with_context(|| format!("{} is not a file", path.display()))?;
+    let mut temp_name = OsString::from(".");
+    temp_name.push(name);
+    temp_name.push(format!(".{:016x}.tmp", rand::random::<u64>()));
+    let temp = path.with_file_name(temp_name);
+
+    let result = write_new(&temp, contents).and_then(|()| {
+        fs::rename(&temp, path).with_context(|| format!("Failed to replace {}", path.display()))
+    });
+    if result.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    result
+}
+
+/// Write a file that must not exist yet, only readable by the current user
+fn write_new(path: &Path, contents: &str) -> Result<()> {
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(path)
+        .with_context(|| format!("Failed to open {}", path.display()))?;
+
+    file.write_all(contents.as_bytes())
+        .and_then(|()| file.sync_all())
+        .with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// Path of the pass entry of a label, relative to the store directory
+///
+/// Namespaces become directories. Labels that would escape the directory,
+/// such as absolute ones or ones with `..` components, are refused.
+pub fn pass_path(label: &str) -> Result<PathBuf> {
+    let unsafe_component =
+        |component: &str| matches!(component, "" | "." | "..") || component.contains('\0');
+    if label.split('/').any(unsafe_component) {
+        bail!("'{}' is not a safe relative path", label);
+    }
+    Ok(PathBuf::from(format!("{}.txt", label)))
+}
+
+/// Write every secret as a pass entry below `dir`, see [`formats::to_pass`]
+///
+/// Directories and files are only accessible by the current user. Secrets
+/// that can't be written are skipped; the reasons are returned.
+pub fn write_pass(dir: &Path, export: &Export) -> Result<Vec<String>> {
+    let mut failures = Vec::new();
+    for (label, data) in export {
+        let path = match pass_path(label) {
+            Ok(path) => dir.join(path),
+            Err(err) => {
+                failures.push(format!("'{}': {:#}", label, err));
+                continue;
+            }
+        };
+        if let Some(reason) = formats::unrepresentable_field(data) {
+            failures.push(format!("'{}': {}", label, reason));
+            continue;
+        }
+
+        if let Some(parent) = path.parent() {
+            DirBuilder::new()
+                .recursive(true)
+                .mode(0o700)
+                .create(parent)
+                .with_context(|| format!("Failed to create {}", parent.display()))?;
+        }
+        write_private(&path, &formats::to_pass(data))?;
+    }
+    Ok(failures)
+}
+
+/// Read the pass entries below `dir`, see [`formats::from_pass`]
+///
+/// Every `.txt` file is read, labelled by its path relative to `dir` without
+/// the extension, e.g. `work/aws` for `work/aws.txt`. Labels are sorted.
+pub fn read_pass(dir: &Path) -> Result<Vec<(String, Secret)>> {
+    let mut entries = Vec::new();
+    let mut pending = vec![dir.to_path_buf()];
+    while let Some(current) = pending.pop() {
+        let listing = fs::read_dir(&current)
+            .with_context(|| format!("Failed to read {}", current.display()))?;
+        for entry in listing {
+            let path = entry
+                .with_context(|| format!("Failed to read {}", current.display()))?
+                .path();
+            if path.is_dir() {
+                pending.push(path);
+            } else if path.extension().is_some_and(|ext| ext == "txt") {
+                let Some(label) = path
+                    .strip_prefix(dir)
+                    .ok()
+                    .and_then(|relative| relative.with_extension("").to_str().map(str::to_string))
+                else {
+                    bail!("{} is not a valid label", path.display());
+                };
+                let text = fs::read_to_string(&path)
+                    .with_context(|| format!("Failed to read {}", path.display()))?;
+                entries.push((label, formats::from_pass(&text)));
+            }
+        }
+    }
+    entries.sort_by(|a, b| a.0.cmp(&b.0));
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+
+    #[test]
+    fn test_round_trip() {
+        let mut secret = Secret::new();
+        secret.insert("username".to_string(), "john".to_string());
+        let export = Export::from([("GitHub".to_string(), secret)]);
+
+        let parsed = from_json(&to_json(&export).unwrap()).unwrap();
+        assert_eq!(parsed["GitHub"].get("username").unwrap().value, "john");
+    }
+
+    #[test]
+    fn test_no_sensitive_export_leaves_out_previous_values() {
+        let mut secret = Secret::new();
+        secret.insert("username".to_string(), "john".to_string());
+        secret.insert("password".to_string(), "old-hunter2".to_string());
+        // As rotated by `rotate`
+        secret.set_previous("password", 1000);
+        secret.insert("password".to_string(), "new-hunter2".to_string());
+
+        let export = Export::from([(
+            "GitHub".to_string(),
+            secret.filter(|field| !field.is_sensitive()),
+        )]);
+        let json = to_json(&export).unwrap();
+        assert!(json.contains("john"));
+        assert!(!json.contains("hunter2"));
+    }
+
+    #[test]
+    fn test_write_replaces_readable_file() {
+        let dir =
+            std::env::temp_dir().join(format!("fuzzel-secrets-export-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("export.json");
+        fs::write(&path, "old").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o644)).unwrap();
+
+        write_private(&path, "new").unwrap();
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "new");
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        // The temporary file was renamed rather than left behind
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_pass_path_refuses_traversal() {
+        assert_eq!(pass_path("work/aws").unwrap(), Path::new("work/aws.txt"));
+        assert_eq!(pass_path("my.bank").unwrap(), Path::new("my.bank.txt"));
+        for label in [
+            "/etc/passwd",
+            "../up",
+            "work/../../up",
+            "work//aws",
+            "a/.",
+            "",
+        ] {
+            assert!(pass_path(label).is_err(), "{}", label);
+        }
+    }
+
+    #[test]
+    fn test_pass_round_trip() {
+        let dir = std::env::temp_dir().join(format!("fuzzel-secrets-pass-{}", std::process::id()));
+        let mut secret = Secret::new();
+        secret.insert("password".to_string(), "hunter2".to_string());
+        secret.insert("username".to_string(), "john".to_string());
+        let mut note = Secret::new();
+        note.insert("note".to_string(), "two\nlines".to_string());
+        let mut spaced = Secret::new();
+        spaced.insert("user name".to_string(), "john".to_string());
+        let export = Export::from([
+            ("work/aws".to_string(), secret.clone()),
+            ("GitHub".to_string(), secret.clone()),
+            ("../escape".to_string(), secret.clone()),
+            ("notes".to_string(), note),
+            ("spaced".to_string(), spaced),
+        ]);
+
+        let failures = write_pass(&dir, &export).unwrap();
+        assert_eq!(failures.len(), 3);
+        assert_eq!(
+            read_pass(&dir).unwrap(),
+            vec![
+                ("GitHub".to_string(), secret.clone()),
+                ("work/aws".to_string(), secret)
+            ]
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_validate() {
+        let mut secret = Secret::new();
+        assert!(validate(&Export::new()).is_err());
+        assert!(validate(&Export::from([("GitHub".to_string(), secret.clone())])).is_err());
+
+        secret.insert("username".to_string(), "john".to_string());
+        assert!(validate(&Export::from([(" ".to_string(), secret.clone())])).is_err());
+        assert!(validate(&Export::from([("GitHub".to_string(), secret)])).is_ok());
+    }
+}
@@ -1,15 +1,98 @@
+use crate::settings;
 use anyhow::{anyhow, Result};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fmt;
+
+/// Number of leading characters shown by partial masking
+const PARTIAL_REVEAL: usize = 2;
+
+/// Length of the mask replacing hidden characters
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MaskLength {
+    /// Always this many mask characters, hiding the length of the value
+    Fixed(usize),
+    /// One mask character per hidden character
+    Proportional,
+}
+
+/// How [`Field::mask_value_with`] masks a value
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MaskPolicy {
+    pub mask_char: char,
+    pub length: MaskLength,
+    /// Number of leading characters left visible, keeping the shape of emails
+    ///
+    /// Values with fewer hidden than shown characters are masked completely,
+    /// as revealing a couple of their characters would give most of them away.
+    pub reveal: Option<usize>,
+}
+
+impl Default for MaskPolicy {
+    /// Eight asterisks, as used for sensitive values
+    fn default() -> Self {
+        Self {
+            mask_char: '*',
+            length: MaskLength::Fixed(8),
+            reveal: None,
+        }
+    }
+}
+
+impl MaskPolicy {
+    /// The policy for fields matching the partial masking patterns, like
+    /// `jo***@***.com`
+    pub fn partial() -> Self {
+        Self {
+            mask_char: '*',
+            length: MaskLength::Fixed(3),
+            reveal: Some(PARTIAL_REVEAL),
+        }
+    }
+
+    /// Mask a value, showing its first `reveal` characters if long enough
+    fn mask(&self, value: &str, reveal: Option<usize>) -> String {
+        let count = value.chars().count();
+        let shown = reveal.filter(|n| count >= 2 * n).unwrap_or(0);
+        let length = match self.length {
+            MaskLength::Fixed(length) => length,
+            MaskLength::Proportional => count - shown,
+        };
+
+        value
+            .chars()
+            .take(shown)
+            .chain(std::iter::repeat_n(self.mask_char, length))
+            .collect()
+    }
+}
 
 /// Represents a field with a key and value, with support for identifying sensitive data
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Field {
     pub key: String,
     pub value: String,
+    /// Explicit sensitivity; `None` falls back to the key heuristic
+    pub sensitive: Option<bool>,
 }
 
 impl Field {
     pub fn new(key: String, value: String) -> Self {
-        Self { key, value }
+        Self {
+            key,
+            value,
+            sensitive: None,
+        }
+    }
+
+    /// Create a field whose sensitivity is forced rather than derived from its
+    /// key, unless `sensitive` is `None`
+    pub fn with_sensitivity(key: String, value: String, sensitive: Option<bool>) -> Self {
+        Self {
+            key,
+            value,
+            sensitive,
+        }
     }
 
     /// Check if a key represents sensitive information
@@ -20,23 +103,59 @@ impl Field {
             || key_lower.contains("token")
     }
 
-    /// Checks if this field contains sensitive information based on its key
+    /// Checks if this field contains sensitive information, based on its key
+    /// unless the sensitivity was set explicitly
     pub fn is_sensitive(&self) -> bool {
-        Self::is_key_sensitive(&self.key)
+        self.sensitive
+            .unwrap_or_else(|| Self::is_key_sensitive(&self.key))
     }
 
-    /// Returns the display value, masking sensitive fields with asterisks
+    /// Check if a key matches one of the partial masking patterns, see
+    /// [`Settings::partial_mask_fields`](settings::Settings::partial_mask_fields)
+    fn is_key_partially_masked(key: &str) -> bool {
+        let key_lower = key.to_lowercase();
+        settings::with(|settings| {
+            settings
+                .partial_mask_fields
+                .iter()
+                .any(|pattern| key_lower.contains(&pattern.to_lowercase()))
+        })
+    }
+
+    /// Returns the display value, masking sensitive fields with asterisks and
+    /// partially masking fields matching the configured patterns
     pub fn display_value(&self) -> String {
         if self.is_sensitive() {
-            "*".repeat(8)
+            self.mask_value_with(&MaskPolicy::default())
+        } else if Self::is_key_partially_masked(&self.key) {
+            self.mask_value_with(&MaskPolicy::partial())
         } else {
             self.value.clone()
         }
     }
 
-    /// Returns the formatted display string "key: value"
+    /// Mask the value according to the policy, regardless of sensitivity
+    ///
+    /// With a partial reveal, emails keep their shape: the domain is masked
+    /// except for its top-level domain, as in `jo***@***.com`.
+    pub fn mask_value_with(&self, policy: &MaskPolicy) -> String {
+        match (policy.reveal, self.value.rsplit_once('@')) {
+            (Some(_), Some((local, domain))) => {
+                let (name, tld) = domain.split_at(domain.rfind('.').unwrap_or(domain.len()));
+                format!(
+                    "{}@{}{}",
+                    policy.mask(local, policy.reveal),
+                    policy.mask(name, None),
+                    tld
+                )
+            }
+            _ => policy.mask(&self.value, policy.reveal),
+        }
+    }
+
+    /// Returns the formatted display string "key: value", same as `to_string()`
     pub fn display(&self) -> String {
-        format!("{}: {}", self.key, self.display_value())
+        self.to_string()
     }
 
     /// Parse a Field from a display string (format: "key: value")
@@ -52,3 +171,203 @@ impl Field {
             .ok_or_else(|| anyhow!("Invalid display format: '{}'", display))
     }
 }
+
+/// Compare field keys for display: keys listed in `order` first, in that
+/// order, then the others alphabetically ignoring case
+pub fn compare_keys(order: &[String], a: &str, b: &str) -> Ordering {
+    let position = |key: &str| order.iter().position(|k| k == key);
+    match (position(a), position(b)) {
+        (Some(a), Some(b)) => a.cmp(&b),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => a
+            .to_lowercase()
+            .cmp(&b.to_lowercase())
+            .then_with(|| a.cmp(b)),
+    }
+}
+
+/// Alternative spellings of common field keys and the key they normalize to
+pub const DEFAULT_KEY_ALIASES: &[(&str, &str)] = &[
+    ("user", "username"),
+    ("login", "username"),
+    ("pw", "password"),
+    ("pass", "password"),
+    ("passwd", "password"),
+    ("e-mail", "email"),
+    ("mail", "email"),
+    ("website", "url"),
+    ("notes", "note"),
+];
+
+/// Normalize a typed field key: trimmed, lowercase and with aliases replaced
+///
+/// `aliases` map keys, ignoring case, to the key they stand for, in addition
+/// to and taking precedence over [`DEFAULT_KEY_ALIASES`].
+pub fn normalize_key(key: &str, aliases: &HashMap<String, String>) -> String {
+    let key = key.trim().to_lowercase();
+    aliases
+        .iter()
+        .find(|(alias, _)| alias.to_lowercase() == key)
+        .map(|(_, target)| target.clone())
+        .or_else(|| {
+            DEFAULT_KEY_ALIASES
+                .iter()
+                .find(|(alias, _)| *alias == key)
+                .map(|(_, target)| target.to_string())
+        })
+        .unwrap_or(key)
+}
+
+/// Format fields as aligned "key  value" lines, padding keys to the longest one
+///
+/// Sensitive values are masked unless `reveal` is set.
+pub fn table(fields: &[Field], reveal: bool) -> Vec<String> {
+    let width = fields
+        .iter()
+        .map(|f| f.key.chars().count())
+        .max()
+        .unwrap_or(0);
+
+    fields
+        .iter()
+        .map(|f| {
+            let value = if reveal {
+                f.value.clone()
+            } else {
+                f.display_value()
+            };
+            format!("{:<width$}  {}", f.key, value, width = width)
+        })
+        .collect()
+}
+
+impl fmt::Display for Field {
+    /// Formats as "key: value", masking sensitive values
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.key, self.display_value())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_string_matches_display() {
+        let username = Field::new("username".to_string(), "john".to_string());
+        let password = Field::new("password".to_string(), "secret".to_string());
+
+        assert_eq!(username.to_string(), username.display());
+        assert_eq!(username.to_string(), "username: john");
+        assert_eq!(password.to_string(), "password: ********");
+    }
+
+    #[test]
+    fn test_explicit_sensitivity() {
+        let key = || "hint".to_string();
+        let value = || "first pet".to_string();
+        assert!(!Field::with_sensitivity(key(), value(), None).is_sensitive());
+        assert_eq!(
+            Field::with_sensitivity(key(), value(), Some(true)).display_value(),
+            "********"
+        );
+
+        let token = Field::with_sensitivity("token".to_string(), value(), Some(false));
+        assert!(!token.is_sensitive());
+        assert_eq!(token.to_string(), "token: first pet");
+    }
+
+    #[test]
+    fn test_compare_keys() {
+        let order = vec!["username".to_string(), "password".to_string()];
+        let mut keys: Vec<&str> = vec!["url", "password", "Note", "api_key", "username"];
+        keys.sort_by(|a, b| compare_keys(&order, a, b));
+        assert_eq!(keys, vec!["username", "password", "api_key", "Note", "url"]);
+
+        let mut keys = vec!["b", "A", "a"];
+        keys.sort_by(|a, b| compare_keys(&[], a, b));
+        assert_eq!(keys, vec!["A", "a", "b"]);
+    }
+
+    #[test]
+    fn test_normalize_key() {
+        let aliases = HashMap::from([
+            ("Acct".to_string(), "account".to_string()),
+            ("mail".to_string(), "username".to_string()),
+        ]);
+        assert_eq!(normalize_key(" User ", &aliases), "username");
+        assert_eq!(normalize_key("PW", &aliases), "password");
+        assert_eq!(normalize_key("acct", &aliases), "account");
+        assert_eq!(normalize_key("Mail", &aliases), "username");
+        assert_eq!(normalize_key("API_Key", &aliases), "api_key");
+    }
+
+    fn masked(value: &str, policy: MaskPolicy) -> String {
+        Field::new("key".to_string(), value.to_string()).mask_value_with(&policy)
+    }
+
+    #[test]
+    fn test_mask_partially() {
+        let partial = MaskPolicy::partial;
+        assert_eq!(masked("john@example.com", partial()), "jo***@***.com");
+        assert_eq!(masked("jo@localhost", partial()), "***@***");
+        assert_eq!(masked("0123456789", partial()), "01***");
+        assert_eq!(masked("1234", partial()), "12***");
+        assert_eq!(masked("123", partial()), "***");
+        assert_eq!(masked("ø", partial()), "***");
+        assert_eq!(masked("", partial()), "***");
+    }
+
+    #[test]
+    fn test_mask_policies() {
+        let policy = |mask_char, length, reveal| MaskPolicy {
+            mask_char,
+            length,
+            reveal,
+        };
+        let fixed = MaskLength::Fixed(4);
+        let proportional = MaskLength::Proportional;
+
+        assert_eq!(masked("hunter2", MaskPolicy::default()), "********");
+        assert_eq!(masked("hunter2", policy('•', fixed, None)), "••••");
+        assert_eq!(
+            masked("hunter2", policy('*', proportional, None)),
+            "*******"
+        );
+        assert_eq!(masked("", policy('*', proportional, None)), "");
+        assert_eq!(masked("a@b.c", policy('*', fixed, None)), "****");
+        assert_eq!(masked("hunter2", policy('#', fixed, Some(2))), "hu####");
+        assert_eq!(
+            masked("hunter2", policy('*', proportional, Some(2))),
+            "hu*****"
+        );
+        assert_eq!(
+            masked("john@example.com", policy('*', proportional, Some(1))),
+            "j***@*******.com"
+        );
+    }
+
+    #[test]
+    fn test_table_aligns_keys() {
+        let fields = vec![
+            Field::new("url".to_string(), "example.com".to_string()),
+            Field::new("password".to_string(), "secret".to_string()),
+        ];
+
+        assert_eq!(
+            table(&fields, false),
+            vec!["url       example.com", "password  ********"]
+        );
+        assert_eq!(table(&fields, true)[1], "password  secret");
+        assert!(table(&[], false).is_empty());
+    }
+
+    #[test]
+    fn test_parse_from_display() {
+        let field = Field::parse_from_display("note: a: b").unwrap();
+        assert_eq!(field.key, "note");
+        assert_eq!(field.value, "a: b");
+        assert!(Field::parse_from_display("no separator").is_err());
+    }
+}
@@ -0,0 +1,156 @@
+use crate::secret::Secret;
+
+/// Field held by the first line of a pass entry
+pub const PASS_PASSWORD_FIELD: &str = "password";
+/// Field collecting the lines of a pass entry that aren't `key: value` pairs
+pub const PASS_NOTE_FIELD: &str = "note";
+
+/// Parse a pass entry: the password on the first line, followed by `key: value`
+/// lines
+///
+/// An empty first line means there is no password. Other lines that aren't
+/// `key: value` pairs, such as free-form notes, are kept in the `note` field.
+pub fn from_pass(text: &str) -> Secret {
+    let mut lines = text.lines();
+    let mut data = Secret::new();
+
+    if let Some(password) = lines.next().filter(|line| !line.is_empty()) {
+        data.insert(PASS_PASSWORD_FIELD.to_string(), password.to_string());
+    }
+
+    let mut notes = Vec::new();
+    for line in lines {
+        match line.split_once(": ") {
+            Some((key, value)) if !key.is_empty() && !key.contains(char::is_whitespace) => {
+                data.insert(key.to_string(), value.to_string());
+            }
+            _ if line.trim().is_empty() => {}
+            _ => notes.push(line),
+        }
+    }
+    if !notes.is_empty() {
+        let note = match data.get_value(PASS_NOTE_FIELD) {
+            Some(note) => format!("{}\n{}", note, notes.join("\n")),
+            None => notes.join("\n"),
+        };
+        data.insert(PASS_NOTE_FIELD.to_string(), note);
+    }
+
+    data
+}
+
+/// Format a secret as a pass entry, see [`from_pass`]
+///
+/// Fields other than the password are written sorted by key; metadata is left
+/// out. Without a password field the first line is empty. Values spanning
+/// several lines and keys containing whitespace can't be read back
+/// faithfully, see [`unrepresentable_field`].
+pub fn to_pass(data: &Secret) -> String {
+    let mut fields = data.fields();
+    fields.sort_by(|a, b| a.key.cmp(&b.key));
+
+    let password = data.get_value(PASS_PASSWORD_FIELD).unwrap_or_default();
+    let mut entry = format!("{}\n", password);
+    for field in fields.iter().filter(|f| f.key != PASS_PASSWORD_FIELD) {
+        entry.push_str(&format!("{}: {}\n", field.key, field.value));
+    }
+    entry
+}
+
+/// Why the first field that can't be read back from a pass entry can't be,
+/// if there is one
+///
+/// Values must fit on a single line. Keys other than the password's must not
+/// contain whitespace, such as in `": "`, or [`from_pass`] would read the
+/// line as a note or split it elsewhere.
+pub fn unrepresentable_field(data: &Secret) -> Option<String> {
+    let mut keys: Vec<&String> = data.keys().collect();
+    keys.sort();
+    keys.into_iter().find_map(|key| {
+        if data
+            .get_value(key)
+            .is_some_and(|value| value.contains('\n'))
+        {
+            Some(format!("'{}' spans several lines", key))
+        } else if key != PASS_PASSWORD_FIELD
+            && (key.is_empty() || key.contains(char::is_whitespace))
+        {
+            Some(format!("'{}' is not a valid pass key", key))
+        } else {
+            None
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn secret(fields: &[(&str, &str)]) -> Secret {
+        let mut data = Secret::new();
+        for (key, value) in fields {
+            data.insert(key.to_string(), value.to_string());
+        }
+        data
+    }
+
+    #[test]
+    fn test_round_trip_preserves_metadata_lines() {
+        let data = secret(&[
+            ("password", "hunter2"),
+            ("username", "john"),
+            ("url", "https://github.com/login?next=a: b"),
+        ]);
+        let entry = to_pass(&data);
+        assert_eq!(
+            entry,
+            "hunter2\nurl: https://github.com/login?next=a: b\nusername: john\n"
+        );
+        assert_eq!(from_pass(&entry), data);
+    }
+
+    #[test]
+    fn test_round_trip_without_password() {
+        let data = secret(&[("username", "john")]);
+        assert_eq!(to_pass(&data), "\nusername: john\n");
+        assert_eq!(from_pass(&to_pass(&data)), data);
+        assert_eq!(from_pass(""), Secret::new());
+    }
+
+    #[test]
+    fn test_from_pass_keeps_free_form_lines_as_note() {
+        let data = from_pass("hunter2\nuser: john\n\nRecovery codes below\nabc def\n");
+        assert_eq!(data.get_value("password"), Some("hunter2"));
+        assert_eq!(data.get_value("user"), Some("john"));
+        assert_eq!(
+            data.get_value("note"),
+            Some("Recovery codes below\nabc def")
+        );
+    }
+
+    #[test]
+    fn test_unrepresentable_field() {
+        let data = secret(&[("password", "x"), ("note", "two\nlines")]);
+        assert_eq!(
+            unrepresentable_field(&data).as_deref(),
+            Some("'note' spans several lines")
+        );
+        assert_eq!(unrepresentable_field(&secret(&[("password", "x")])), None);
+    }
+
+    #[test]
+    fn test_keys_with_whitespace_are_unrepresentable() {
+        for key in ["user name", "a: b", "tab\tkey", ""] {
+            let data = secret(&[("password", "x"), (key, "john")]);
+            // Written as is, the field would not be read back
+            assert_ne!(from_pass(&to_pass(&data)), data, "{:?}", key);
+            assert_eq!(
+                unrepresentable_field(&data),
+                Some(format!("'{}' is not a valid pass key", key))
+            );
+        }
+        let data = secret(&[("password", "x"), ("user:name", "john: doe")]);
+        assert_eq!(unrepresentable_field(&data), None);
+        assert_eq!(from_pass(&to_pass(&data)), data);
+    }
+}
@@ -1,79 +1,278 @@
+use crate::display;
+use crate::settings;
 use anyhow::{Context, Result};
-use std::io::Write;
-use std::process::{Command, Stdio};
+use std::fmt;
+use std::io::{BufWriter, ErrorKind, Write};
+use std::process::{Child, ChildStdin, Command, ExitStatus, Output, Stdio};
+use std::thread::{self, ScopedJoinHandle};
+use std::time::{Duration, Instant};
+
+/// Program run for prompts, unless overridden with [`FUZZEL_BIN_VAR`]
+const FUZZEL_BIN: &str = "fuzzel";
+/// Environment variable naming the fuzzel program to run instead of `fuzzel`
+pub const FUZZEL_BIN_VAR: &str = "FUZZEL_SECRETS_FUZZEL_BIN";
+
+/// Error returned when a prompt is needed but prompts are disabled, see
+/// [`Settings::interactive`](crate::settings::Settings::interactive)
+///
+/// Scripts fail fast with it rather than hang on a prompt nobody answers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NotInteractive;
+
+impl fmt::Display for NotInteractive {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "interactive prompt required but --non-interactive set")
+    }
+}
 
-/// Let the user provide a password without any suggestions
-pub fn request_password(placeholder: Option<&str>) -> Result<String> {
-    let mut cmd = Command::new("fuzzel");
-    cmd.args(["--dmenu", "--lines", "0"]);
+impl std::error::Error for NotInteractive {}
 
-    if let Some(ph) = placeholder {
-        cmd.arg("--placeholder").arg(ph);
+/// Fail if prompts are disabled
+pub fn require_interactive() -> Result<(), NotInteractive> {
+    if settings::with(|settings| settings.interactive) {
+        Ok(())
+    } else {
+        Err(NotInteractive)
     }
+}
 
-    cmd.arg("--password");
+/// Error returned when the user dismisses fuzzel without making a choice
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cancelled;
 
-    let output = cmd.output().context("Failed to execute fuzzel")?;
+impl fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Cancelled by user")
+    }
+}
+
+impl std::error::Error for Cancelled {}
 
-    if !output.status.success() {
-        anyhow::bail!("fuzzel command failed");
+/// Map fuzzel's exit status to an error. Fuzzel exits with 1 when the user aborts.
+fn check_status(status: ExitStatus) -> Result<()> {
+    match status.code() {
+        Some(0) => Ok(()),
+        Some(1) => Err(Cancelled.into()),
+        _ => anyhow::bail!("fuzzel command failed"),
     }
+}
 
-    let result = String::from_utf8(output.stdout).context("Failed to parse fuzzel output")?;
+/// Start building a fuzzel invocation, failing if prompts are disabled or there
+/// is no display to show it on
+fn command() -> Result<Command> {
+    require_interactive()?;
+    display::require()?;
+    settings::with(|settings| {
+        let mut cmd = Command::new(
+            settings
+                .fuzzel_bin
+                .as_deref()
+                .unwrap_or(FUZZEL_BIN.as_ref()),
+        );
+        if let Some(path) = &settings.fuzzel_config {
+            cmd.arg("--config").arg(path);
+        }
+        if !settings.fuzzel_cache {
+            cmd.args(["--cache", "/dev/null"]);
+        }
+        if let Some(width) = settings.fuzzel_width {
+            cmd.arg("--width").arg(width.to_string());
+        }
+        Ok(cmd)
+    })
+}
 
-    Ok(result.trim().to_string())
+/// Start a fuzzel invocation, explaining how to fix a missing fuzzel
+fn spawn(cmd: &mut Command) -> Result<Child> {
+    cmd.spawn().map_err(|err| {
+        if err.kind() != ErrorKind::NotFound {
+            anyhow::Error::new(err).context("Failed to execute fuzzel")
+        } else if cmd.get_program() == FUZZEL_BIN {
+            anyhow::anyhow!(
+                "fuzzel not found in PATH; install fuzzel or set {}",
+                FUZZEL_BIN_VAR
+            )
+        } else {
+            anyhow::anyhow!(
+                "'{}' not found; check {}",
+                cmd.get_program().to_string_lossy(),
+                FUZZEL_BIN_VAR
+            )
+        }
+    })
 }
 
-/// Let the user provide input without any suggestions
-pub fn request_input(placeholder: Option<&str>) -> Result<String> {
-    let mut cmd = Command::new("fuzzel");
+/// Decode fuzzel's output
+///
+/// Trimmed output drops all surrounding whitespace, which suits labels and menu
+/// selections. Untrimmed output only drops the newline fuzzel terminates its
+/// output with, so values with intentional leading or trailing spaces survive.
+fn parse_output(stdout: Vec<u8>, trim: bool) -> Result<String> {
+    let result = String::from_utf8(stdout).context("Failed to parse fuzzel output")?;
+
+    if trim {
+        Ok(result.trim().to_string())
+    } else {
+        Ok(result
+            .strip_suffix('\n')
+            .map(str::to_string)
+            .unwrap_or(result))
+    }
+}
+
+/// Let the user type a value without any suggestions, starting from `initial`
+fn request(
+    placeholder: Option<&str>,
+    initial: Option<&str>,
+    password: bool,
+    trim: bool,
+) -> Result<String> {
+    let mut cmd = command()?;
     cmd.args(["--dmenu", "--lines", "0"]);
 
     if let Some(ph) = placeholder {
         cmd.arg("--placeholder").arg(ph);
     }
 
-    let output = cmd.output().context("Failed to execute fuzzel")?;
+    if let Some(initial) = initial {
+        cmd.arg("--search").arg(initial);
+    }
 
-    if !output.status.success() {
-        anyhow::bail!("fuzzel command failed");
+    if password {
+        cmd.arg("--password");
     }
 
-    let result = String::from_utf8(output.stdout).context("Failed to parse fuzzel output")?;
+    cmd.stdin(Stdio::null());
+    cmd.stdout(Stdio::piped());
+    let output = spawn(&mut cmd)?
+        .wait_with_output()
+        .context("Failed to wait for fuzzel")?;
 
-    Ok(result.trim().to_string())
+    check_status(output.status)?;
+
+    parse_output(output.stdout, trim)
 }
 
-/// Select one of the items with Fuzzel, returning the index
-pub fn select_index(items: &[String], placeholder: Option<&str>) -> Result<usize> {
-    let input_data = items.join("\n");
+/// Let the user provide a password without any suggestions, trimming whitespace
+pub fn request_password(placeholder: Option<&str>) -> Result<String> {
+    request(placeholder, None, true, true)
+}
 
-    let mut cmd = Command::new("fuzzel");
-    cmd.args(["--dmenu", "--index"]);
+/// Let the user provide input without any suggestions, trimming whitespace
+pub fn request_input(placeholder: Option<&str>) -> Result<String> {
+    request(placeholder, None, false, true)
+}
 
-    if let Some(ph) = placeholder {
-        cmd.arg("--placeholder").arg(ph);
-    }
+/// Let the user provide a password, keeping surrounding whitespace
+pub fn request_password_raw(placeholder: Option<&str>) -> Result<String> {
+    request(placeholder, None, true, false)
+}
 
+/// Let the user provide input, keeping surrounding whitespace
+pub fn request_input_raw(placeholder: Option<&str>) -> Result<String> {
+    request(placeholder, None, false, false)
+}
+
+/// Let the user edit a value in place, keeping surrounding whitespace
+///
+/// The value is shown in plain text, so only use this for values that aren't
+/// sensitive.
+pub fn request_input_prefilled(value: &str, placeholder: Option<&str>) -> Result<String> {
+    request(placeholder, Some(value), false, false)
+}
+
+/// Attach an icon to a dmenu item, using fuzzel's `\0icon\x1f` escape
+///
+/// Only the text is printed when the item is selected, but items with icons
+/// should be selected by index so the escape never ends up in a result.
+pub fn with_icon(item: &str, icon: &str) -> String {
+    format!("{}\0icon\x1f{}", item, icon)
+}
+
+/// Run fuzzel with the items on stdin, one per line, and collect its output
+///
+/// The items are streamed from a separate thread while the output is read, so
+/// a long list can't deadlock with fuzzel blocking on a full stdout pipe.
+fn run_with_items(mut cmd: Command, items: &[String]) -> Result<Output> {
     cmd.stdin(Stdio::piped());
     cmd.stdout(Stdio::piped());
 
-    let mut child = cmd.spawn().context("Failed to spawn fuzzel")?;
+    let mut child = spawn(&mut cmd)?;
+    let stdin = child.stdin.take();
+
+    thread::scope(|scope| {
+        let writer = scope.spawn(move || write_items(stdin, items));
+        let output = child
+            .wait_with_output()
+            .context("Failed to wait for fuzzel")?;
+        join_writer(writer)?;
+        Ok(output)
+    })
+}
 
-    if let Some(mut stdin) = child.stdin.take() {
-        stdin
-            .write_all(input_data.as_bytes())
-            .context("Failed to write to fuzzel stdin")?;
+fn join_writer(writer: ScopedJoinHandle<'_, Result<()>>) -> Result<()> {
+    writer
+        .join()
+        .map_err(|_| anyhow::anyhow!("Writing to fuzzel stdin panicked"))?
+}
+
+/// Write the items to fuzzel's stdin, closing it when done
+fn write_items(stdin: Option<ChildStdin>, items: &[String]) -> Result<()> {
+    let Some(stdin) = stdin else {
+        return Ok(());
+    };
+
+    let mut writer = BufWriter::new(stdin);
+    let result = items
+        .iter()
+        .enumerate()
+        .try_for_each(|(i, item)| {
+            if i > 0 {
+                writer.write_all(b"\n")?;
+            }
+            writer.write_all(item.as_bytes())
+        })
+        .and_then(|()| writer.flush());
+
+    match result {
+        // fuzzel may exit before reading all items, e.g. when one is picked early
+        Err(err) if err.kind() == ErrorKind::BrokenPipe => Ok(()),
+        result => result.context("Failed to write to fuzzel stdin"),
     }
+}
 
-    let output = child
-        .wait_with_output()
-        .context("Failed to wait for fuzzel")?;
+/// Environment variable making selections pick the first item without
+/// running fuzzel, so scripted flows can be tested without a display
+const AUTOSELECT_VAR: &str = "FUZZEL_SECRETS_AUTOSELECT";
+
+/// Check if selections are made automatically, see [`AUTOSELECT_VAR`]
+///
+/// This is a testing aid, not meant for regular use.
+fn autoselect() -> bool {
+    std::env::var_os(AUTOSELECT_VAR).is_some_and(|value| !value.is_empty() && value != "0")
+}
+
+/// Select one of the items with Fuzzel, returning the index
+pub fn select_index(items: &[String], placeholder: Option<&str>) -> Result<usize> {
+    if autoselect() {
+        return if items.is_empty() {
+            Err(Cancelled.into())
+        } else {
+            Ok(0)
+        };
+    }
 
-    if !output.status.success() {
-        anyhow::bail!("fuzzel command failed");
+    let mut cmd = command()?;
+    cmd.args(["--dmenu", "--index"]);
+
+    if let Some(ph) = placeholder {
+        cmd.arg("--placeholder").arg(ph);
     }
 
+    let output = run_with_items(cmd, items)?;
+
+    check_status(output.status)?;
+
     let result = String::from_utf8(output.stdout).context("Failed to parse fuzzel output")?;
 
     result
@@ -91,11 +290,76 @@ pub fn select(items: &[String], placeholder: Option<&str>) -> Result<String> {
         .ok_or_else(|| anyhow::anyhow!("Invalid index returned from fuzzel"))
 }
 
+/// Ask a yes/no question, treating cancelling as no
+///
+/// "No" is listed first, so accepting the prompt without choosing is safe.
+pub fn confirm(question: &str) -> Result<bool> {
+    let choices = ["No".to_string(), "Yes".to_string()];
+    match select(&choices, Some(question)) {
+        Ok(answer) => Ok(answer == "Yes"),
+        Err(err) if err.is::<Cancelled>() => Ok(false),
+        Err(err) => Err(err),
+    }
+}
+
 /// Select from suggestions or type a new value
 pub fn select_or_input(items: &[String], placeholder: Option<&str>) -> Result<String> {
-    let input_data = items.join("\n");
+    let mut cmd = command()?;
+    cmd.arg("--dmenu");
 
-    let mut cmd = Command::new("fuzzel");
+    if let Some(ph) = placeholder {
+        cmd.arg("--placeholder").arg(ph);
+    }
+
+    let output = run_with_items(cmd, items)?;
+
+    check_status(output.status)?;
+
+    let result = String::from_utf8(output.stdout).context("Failed to parse fuzzel output")?;
+
+    Ok(result.trim().to_string())
+}
+
+/// Select several of the items with Fuzzel
+///
+/// This relies on fuzzel's multi-select keybinding printing one line per
+/// selected item; without it, a single item is returned. The items aren't
+/// selected by index, since several of them are returned. Each line is
+/// trimmed, so accepting an empty input yields a single empty string, while no
+/// output at all is a [`Cancelled`] error.
+pub fn select_multi(items: &[String], placeholder: Option<&str>) -> Result<Vec<String>> {
+    let mut cmd = command()?;
+    cmd.arg("--dmenu");
+
+    if let Some(ph) = placeholder {
+        cmd.arg("--placeholder").arg(ph);
+    }
+
+    let output = run_with_items(cmd, items)?;
+
+    check_status(output.status)?;
+
+    parse_lines(output.stdout)
+}
+
+/// Split fuzzel's output into one trimmed selection per line
+fn parse_lines(stdout: Vec<u8>) -> Result<Vec<String>> {
+    let result = String::from_utf8(stdout).context("Failed to parse fuzzel output")?;
+
+    if result.is_empty() {
+        return Err(Cancelled.into());
+    }
+
+    Ok(result.lines().map(|line| line.trim().to_string()).collect())
+}
+
+/// Show lines in Fuzzel until the user dismisses them or the timeout expires
+pub fn message(
+    lines: &[String],
+    placeholder: Option<&str>,
+    timeout: Option<Duration>,
+) -> Result<()> {
+    let mut cmd = command()?;
     cmd.arg("--dmenu");
 
     if let Some(ph) = placeholder {
@@ -103,25 +367,152 @@ pub fn select_or_input(items: &[String], placeholder: Option<&str>) -> Result<St
     }
 
     cmd.stdin(Stdio::piped());
-    cmd.stdout(Stdio::piped());
+    cmd.stdout(Stdio::null());
+
+    let mut child = spawn(&mut cmd)?;
+    let stdin = child.stdin.take();
+
+    // Write from a thread as in run_with_items, so the timeout also applies
+    // while lines are still being written
+    thread::scope(|scope| {
+        let writer = scope.spawn(move || write_items(stdin, lines));
+        match timeout {
+            Some(timeout) => wait_with_timeout(&mut child, timeout)?,
+            None => {
+                child.wait().context("Failed to wait for fuzzel")?;
+            }
+        }
+        join_writer(writer)
+    })
+}
+
+/// Watchdog killing fuzzel if it is still open once the timeout expires
+fn wait_with_timeout(child: &mut Child, timeout: Duration) -> Result<()> {
+    let deadline = Instant::now() + timeout;
+
+    while child
+        .try_wait()
+        .context("Failed to wait for fuzzel")?
+        .is_none()
+    {
+        if Instant::now() >= deadline {
+            child.kill().context("Failed to close fuzzel")?;
+            child.wait().context("Failed to wait for fuzzel")?;
+            break;
+        }
+        thread::sleep(Duration::from_millis(50));
+    }
 
-    let mut child = cmd.spawn().context("Failed to spawn fuzzel")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    if let Some(mut stdin) = child.stdin.take() {
-        stdin
-            .write_all(input_data.as_bytes())
-            .context("Failed to write to fuzzel stdin")?;
+    #[test]
+    fn test_parse_output_trimmed() {
+        assert_eq!(
+            parse_output(b" GitHub \n".to_vec(), true).unwrap(),
+            "GitHub"
+        );
     }
 
-    let output = child
-        .wait_with_output()
-        .context("Failed to wait for fuzzel")?;
+    #[test]
+    fn test_with_icon() {
+        assert_eq!(
+            with_icon("GitHub", "web-browser"),
+            "GitHub\0icon\x1fweb-browser"
+        );
+    }
 
-    if !output.status.success() {
-        anyhow::bail!("fuzzel command failed");
+    #[test]
+    fn test_parse_lines() {
+        assert_eq!(
+            parse_lines(b"GitHub\n Gmail \n".to_vec()).unwrap(),
+            vec!["GitHub", "Gmail"]
+        );
+        assert_eq!(parse_lines(b"\n".to_vec()).unwrap(), vec![""]);
+        assert!(parse_lines(Vec::new()).unwrap_err().is::<Cancelled>());
     }
 
-    let result = String::from_utf8(output.stdout).context("Failed to parse fuzzel output")?;
+    #[test]
+    fn test_run_with_many_items_and_slow_reader() {
+        let items: Vec<String> = (0..20_000).map(|i| format!("secret-{:05}", i)).collect();
+        let mut cmd = Command::new("sh");
+        cmd.args(["-c", "sleep 0.2; cat"]);
 
-    Ok(result.trim().to_string())
+        let output = run_with_items(cmd, &items).unwrap();
+        assert!(output.status.success());
+        assert_eq!(String::from_utf8(output.stdout).unwrap(), items.join("\n"));
+    }
+
+    #[test]
+    fn test_run_with_interleaving_child() {
+        // Echoes every line as soon as it is read, filling its stdout pipe long
+        // before all input is written
+        let items: Vec<String> = (0..5_000).map(|i| format!("{:0>100}", i)).collect();
+        let mut cmd = Command::new("sh");
+        cmd.args([
+            "-c",
+            "while IFS= read -r line; do printf '%s\\n' \"$line\"; done",
+        ]);
+
+        let output = run_with_items(cmd, &items).unwrap();
+        let lines: Vec<&str> = std::str::from_utf8(&output.stdout)
+            .unwrap()
+            .lines()
+            .collect();
+        // The last item isn't newline-terminated, so `read` doesn't echo it
+        assert_eq!(lines, items[..items.len() - 1]);
+    }
+
+    #[test]
+    fn test_run_tolerates_early_exit() {
+        let items: Vec<String> = (0..20_000).map(|i| format!("secret-{:05}", i)).collect();
+        let mut cmd = Command::new("sh");
+        cmd.args(["-c", "head -n 1"]);
+
+        let output = run_with_items(cmd, &items).unwrap();
+        assert_eq!(output.stdout, b"secret-00000\n");
+    }
+
+    #[test]
+    fn test_autoselect_picks_first_item() {
+        let _guard = settings::test_guard();
+        std::env::set_var(AUTOSELECT_VAR, "1");
+        let items = vec!["GitHub".to_string(), "Gmail".to_string()];
+        let selected = select(&items, Some("Select secret"));
+        let empty = select(&[], None);
+        let confirmed = confirm("Delete 'GitHub'?");
+        std::env::remove_var(AUTOSELECT_VAR);
+
+        assert_eq!(selected.unwrap(), "GitHub");
+        assert!(empty.unwrap_err().is::<Cancelled>());
+        assert!(!confirmed.unwrap());
+    }
+
+    #[test]
+    fn test_spawn_explains_missing_fuzzel() {
+        let err = spawn(&mut Command::new(FUZZEL_BIN_VAR)).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!("'{}' not found; check {}", FUZZEL_BIN_VAR, FUZZEL_BIN_VAR)
+        );
+    }
+
+    #[test]
+    fn test_non_interactive_refuses_prompts() {
+        let _guard = settings::test_guard();
+        settings::update(|settings| settings.interactive = false);
+        let err = request_input(Some("Label")).unwrap_err();
+        settings::update(|settings| settings.interactive = true);
+        assert!(err.is::<NotInteractive>());
+    }
+
+    #[test]
+    fn test_parse_output_raw_keeps_whitespace() {
+        assert_eq!(parse_output(b"abc \n".to_vec(), false).unwrap(), "abc ");
+        assert_eq!(parse_output(b" abc".to_vec(), false).unwrap(), " abc");
+    }
 }
@@ -0,0 +1,220 @@
+use rand::rngs::OsRng;
+use rand::Rng;
+use serde::Deserialize;
+use std::fmt;
+
+/// Rough strength rating of a secret value
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Rating {
+    VeryWeak,
+    Weak,
+    Fair,
+    Strong,
+}
+
+impl fmt::Display for Rating {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let rating = match self {
+            Rating::VeryWeak => "very weak",
+            Rating::Weak => "weak",
+            Rating::Fair => "fair",
+            Rating::Strong => "strong",
+        };
+        write!(f, "{}", rating)
+    }
+}
+
+/// Strength estimate of a value, never containing the value itself
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Strength {
+    pub length: usize,
+    pub lowercase: bool,
+    pub uppercase: bool,
+    pub digits: bool,
+    pub symbols: bool,
+    /// Estimated entropy assuming characters are drawn randomly from the used classes
+    pub entropy_bits: f64,
+}
+
+impl Strength {
+    /// Number of character classes used
+    pub fn classes(&self) -> usize {
+        [self.lowercase, self.uppercase, self.digits, self.symbols]
+            .iter()
+            .filter(|used| **used)
+            .count()
+    }
+
+    pub fn rating(&self) -> Rating {
+        match self.entropy_bits {
+            bits if bits < 28.0 => Rating::VeryWeak,
+            bits if bits < 36.0 => Rating::Weak,
+            bits if bits < 60.0 => Rating::Fair,
+            _ => Rating::Strong,
+        }
+    }
+}
+
+impl fmt::Display for Strength {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} chars, {}", self.length, self.rating())
+    }
+}
+
+/// Estimate the strength of a value from its length and character classes
+pub fn strength(value: &str) -> Strength {
+    let length = value.chars().count();
+    let lowercase = value.chars().any(|c| c.is_ascii_lowercase());
+    let uppercase = value.chars().any(|c| c.is_ascii_uppercase());
+    let digits = value.chars().any(|c| c.is_ascii_digit());
+    let symbols = value.chars().any(|c| !c.is_ascii_alphanumeric());
+
+    let pool: u32 = [
+        (lowercase, 26),
+        (uppercase, 26),
+        (digits, 10),
+        (symbols, 33),
+    ]
+    .iter()
+    .filter(|(used, _)| *used)
+    .map(|(_, size)| size)
+    .sum();
+
+    let entropy_bits = if pool == 0 {
+        0.0
+    } else {
+        length as f64 * f64::from(pool).log2()
+    };
+
+    Strength {
+        length,
+        lowercase,
+        uppercase,
+        digits,
+        symbols,
+        entropy_bits,
+    }
+}
+
+/// Characters generated values are drawn from
+const GENERATED_CHARS: &[u8] =
+    b"abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789!#$%&()*+,-./:;<=>?@[]^_{}~";
+
+/// Generate a random value of the given length from the OS random source
+///
+/// Values of at least four characters contain every character class, so they
+/// pass any [`PasswordPolicy`] of at most that length.
+pub fn generate(length: usize) -> String {
+    loop {
+        let value: String = (0..length)
+            .map(|_| char::from(GENERATED_CHARS[OsRng.gen_range(0..GENERATED_CHARS.len())]))
+            .collect();
+        if length < 4 || strength(&value).classes() == 4 {
+            return value;
+        }
+    }
+}
+
+/// Requirements for sensitive values, loaded from the configuration
+///
+/// The default policy accepts everything.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct PasswordPolicy {
+    pub min_length: usize,
+    pub require_digit: bool,
+    pub require_symbol: bool,
+    pub require_upper: bool,
+}
+
+impl PasswordPolicy {
+    /// Describe every requirement the value fails, without revealing the value
+    pub fn violations(&self, value: &str) -> Vec<String> {
+        let strength = strength(value);
+        let mut violations = Vec::new();
+
+        if strength.length < self.min_length {
+            violations.push(format!("shorter than {} chars", self.min_length));
+        }
+        if self.require_digit && !strength.digits {
+            violations.push("no digit".to_string());
+        }
+        if self.require_symbol && !strength.symbols {
+            violations.push("no symbol".to_string());
+        }
+        if self.require_upper && !strength.uppercase {
+            violations.push("no uppercase letter".to_string());
+        }
+
+        violations
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_strength_classes() {
+        let s = strength("abcD3!");
+        assert_eq!(s.length, 6);
+        assert_eq!(s.classes(), 4);
+    }
+
+    #[test]
+    fn test_strength_rating() {
+        assert_eq!(strength("").rating(), Rating::VeryWeak);
+        assert_eq!(strength("dragon").rating(), Rating::Weak);
+        assert_eq!(strength("correcthorse").rating(), Rating::Fair);
+        assert_eq!(strength("X9!fk2#Lq8$wZ4pT@r7v").rating(), Rating::Strong);
+    }
+
+    #[test]
+    fn test_generate() {
+        let policy = PasswordPolicy {
+            min_length: 20,
+            require_digit: true,
+            require_symbol: true,
+            require_upper: true,
+        };
+        for _ in 0..20 {
+            let value = generate(20);
+            assert_eq!(value.len(), 20);
+            assert!(policy.violations(&value).is_empty());
+        }
+        assert_ne!(generate(20), generate(20));
+        assert_eq!(generate(2).len(), 2);
+    }
+
+    #[test]
+    fn test_default_policy_accepts_everything() {
+        assert!(PasswordPolicy::default().violations("").is_empty());
+    }
+
+    #[test]
+    fn test_policy_violations() {
+        let policy = PasswordPolicy {
+            min_length: 12,
+            require_digit: true,
+            require_symbol: true,
+            require_upper: true,
+        };
+
+        assert_eq!(
+            policy.violations("password"),
+            vec![
+                "shorter than 12 chars",
+                "no digit",
+                "no symbol",
+                "no uppercase letter"
+            ]
+        );
+        assert!(policy.violations("Correct-horse-9").is_empty());
+    }
+
+    #[test]
+    fn test_display_hides_value() {
+        let display = strength("X9!fk2#Lq8$wZ4pT@r7v").to_string();
+        assert_eq!(display, "20 chars, strong");
+    }
+}
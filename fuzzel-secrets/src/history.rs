@@ -0,0 +1,158 @@
+use crate::xdg;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::fs::{self, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::PathBuf;
+
+/// Maximum number of labels to remember the last used field for
+const MAX_FIELD_ENTRIES: usize = 200;
+
+/// Usage history, stored in `$XDG_STATE_HOME/fuzzel-secrets/history.json`
+///
+/// Only labels and field keys are recorded, never values.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct History {
+    /// Last retrieved field per label, most recently used first
+    last_fields: Vec<(String, String)>,
+}
+
+impl History {
+    /// Location of the history file
+    pub fn path() -> Option<PathBuf> {
+        xdg::state_dir().map(|dir| dir.join("history.json"))
+    }
+
+    /// Load the history, starting empty if it doesn't exist
+    pub fn load() -> Result<History> {
+        let Some(path) = Self::path() else {
+            return Ok(History::default());
+        };
+
+        match fs::read_to_string(&path) {
+            Ok(contents) => serde_json::from_str(&contents)
+                .with_context(|| format!("Failed to parse history file {}", path.display())),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(History::default()),
+            Err(err) => {
+                Err(err).with_context(|| format!("Failed to read history file {}", path.display()))
+            }
+        }
+    }
+
+    /// Save the history, readable only by the current user
+    pub fn save(&self) -> Result<()> {
+        let path = Self::path().context("Failed to determine history location")?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)
+                .with_context(|| format!("Failed to create {}", dir.display()))?;
+        }
+
+        let json = serde_json::to_string(self).context("Failed to serialize history")?;
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .mode(0o600)
+            .open(&path)
+            .with_context(|| format!("Failed to open {}", path.display()))?;
+        file.write_all(json.as_bytes())
+            .with_context(|| format!("Failed to write {}", path.display()))
+    }
+
+    /// The field last retrieved from a secret
+    pub fn last_field(&self, label: &str) -> Option<&str> {
+        self.last_fields
+            .iter()
+            .find(|(l, _)| l == label)
+            .map(|(_, field)| field.as_str())
+    }
+
+    /// Remember the field retrieved from a secret
+    pub fn record_field(&mut self, label: &str, field: &str) {
+        self.last_fields.retain(|(l, _)| l != label);
+        self.last_fields
+            .insert(0, (label.to_string(), field.to_string()));
+        self.last_fields.truncate(MAX_FIELD_ENTRIES);
+    }
+
+    /// How recently a field was retrieved from a secret, 0 being the most recent
+    pub fn recency(&self, label: &str) -> Option<usize> {
+        self.last_fields.iter().position(|(l, _)| l == label)
+    }
+
+    /// Compare labels by recent use: most recently retrieved first, then
+    /// the never retrieved ones alphabetically
+    pub fn compare_recent(&self, a: &str, b: &str) -> Ordering {
+        match (self.recency(a), self.recency(b)) {
+            (Some(a), Some(b)) => a.cmp(&b),
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => a.cmp(b),
+        }
+    }
+
+    /// Move the field last retrieved from a secret to the front
+    pub fn order_fields(&self, label: &str, fields: &mut [String]) {
+        if let Some(last) = self.last_field(label) {
+            if let Some(pos) = fields.iter().position(|f| f == last) {
+                fields[..=pos].rotate_right(1);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_field_replaces_previous() {
+        let mut history = History::default();
+        history.record_field("GitHub", "username");
+        history.record_field("GitHub", "password");
+        assert_eq!(history.last_field("GitHub"), Some("password"));
+        assert_eq!(history.last_fields.len(), 1);
+    }
+
+    #[test]
+    fn test_record_field_is_bounded() {
+        let mut history = History::default();
+        for i in 0..MAX_FIELD_ENTRIES + 10 {
+            history.record_field(&format!("label{}", i), "password");
+        }
+        assert_eq!(history.last_fields.len(), MAX_FIELD_ENTRIES);
+        assert_eq!(history.last_field("label0"), None);
+    }
+
+    #[test]
+    fn test_compare_recent() {
+        let mut history = History::default();
+        history.record_field("GitLab", "password");
+        history.record_field("GitHub", "password");
+
+        let mut labels = vec!["Gmail", "GitLab", "AWS", "GitHub"];
+        labels.sort_by(|a, b| history.compare_recent(a, b));
+        assert_eq!(labels, vec!["GitHub", "GitLab", "AWS", "Gmail"]);
+    }
+
+    #[test]
+    fn test_order_fields() {
+        let mut history = History::default();
+        history.record_field("GitHub", "password");
+
+        let mut fields = vec![
+            "email".to_string(),
+            "password".to_string(),
+            "username".to_string(),
+        ];
+        history.order_fields("GitHub", &mut fields);
+        assert_eq!(fields, vec!["password", "email", "username"]);
+
+        let mut other = fields.clone();
+        history.order_fields("GitLab", &mut other);
+        assert_eq!(other, fields);
+    }
+}
@@ -0,0 +1,185 @@
+use crate::label;
+use crate::secret::Secret;
+use anyhow::{bail, Result};
+
+/// Name of the group KeePass puts every entry below, left out of labels
+const ROOT_GROUP: &str = "Root";
+
+/// KeePass CSV columns and the fields they are stored as
+const COLUMN_FIELDS: &[(&str, &str)] = &[
+    ("Username", "username"),
+    ("Password", "password"),
+    ("URL", "url"),
+    ("Notes", "note"),
+];
+
+/// Secrets read from a KeePass CSV export, and the rows that couldn't be read
+#[derive(Debug, Default)]
+pub struct Import {
+    pub secrets: Vec<(String, Secret)>,
+    pub failures: Vec<String>,
+}
+
+/// Read a CSV export of KeePassXC
+///
+/// Each row becomes a secret labelled by its Title, below its Group as a
+/// namespace, e.g. `work/aws` for the entry `aws` in `Root/work`. The Username,
+/// Password, URL and Notes columns become the `username`, `password`, `url` and
+/// `note` fields; empty values and other columns are left out.
+pub fn parse(text: &str) -> Result<Import> {
+    let mut records = parse_csv(text)?.into_iter();
+    let Some(header) = records.next() else {
+        bail!("CSV file is empty");
+    };
+    let column = |name: &str| {
+        header
+            .iter()
+            .position(|column| column.trim().eq_ignore_ascii_case(name))
+    };
+    let Some(title_column) = column("Title") else {
+        bail!("CSV file has no Title column, is it a KeePass export?");
+    };
+    let group_column = column("Group");
+    let field_columns: Vec<(usize, &str)> = COLUMN_FIELDS
+        .iter()
+        .filter_map(|(name, key)| column(name).map(|index| (index, *key)))
+        .collect();
+
+    let mut import = Import::default();
+    for (index, record) in records.enumerate() {
+        // The header is row 1
+        let row = index + 2;
+        if record.len() != header.len() {
+            import.failures.push(format!(
+                "Row {}: expected {} columns, found {}",
+                row,
+                header.len(),
+                record.len()
+            ));
+            continue;
+        }
+
+        let title = record[title_column].trim();
+        if title.is_empty() {
+            import.failures.push(format!("Row {}: empty title", row));
+            continue;
+        }
+        let group = group_column.map_or("", |column| group_namespace(&record[column]));
+
+        let mut data = Secret::new();
+        for (column, key) in &field_columns {
+            if !record[*column].is_empty() {
+                data.insert(key.to_string(), record[*column].clone());
+            }
+        }
+        import
+            .secrets
+            .push((label::with_prefix(group, title), data));
+    }
+
+    Ok(import)
+}
+
+/// The namespace of a KeePass group, without the root group
+fn group_namespace(group: &str) -> &str {
+    let group = group.trim().trim_matches(label::NAMESPACE_SEPARATOR);
+    match group.strip_prefix(ROOT_GROUP) {
+        Some("") => "",
+        Some(rest) if rest.starts_with(label::NAMESPACE_SEPARATOR) => &rest[1..],
+        _ => group,
+    }
+}
+
+/// Split CSV text into records of fields
+///
+/// Fields may be quoted with `"`, in which case they can contain commas, line
+/// breaks and `""` for a quote. Records end with `\n` or `\r\n`; blank lines
+/// are skipped.
+fn parse_csv(text: &str) -> Result<Vec<Vec<String>>> {
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match (quoted, c) {
+            (true, '"') if chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            (true, '"') => quoted = false,
+            (true, c) => field.push(c),
+            (false, '"') if field.is_empty() => quoted = true,
+            (false, ',') => record.push(std::mem::take(&mut field)),
+            (false, '\r') if chars.peek() == Some(&'\n') => {}
+            (false, '\n') => {
+                record.push(std::mem::take(&mut field));
+                let done = std::mem::take(&mut record);
+                if done != [""] {
+                    records.push(done);
+                }
+            }
+            (false, c) => field.push(c),
+        }
+    }
+
+    if quoted {
+        bail!("CSV file ends inside a quoted field");
+    }
+    record.push(field);
+    if record != [""] {
+        records.push(record);
+    }
+
+    Ok(records)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_csv_quoting() {
+        let records = parse_csv("a,\"b,c\",\"say \"\"hi\"\"\"\r\n\n\"multi\nline\",,x\n").unwrap();
+        assert_eq!(
+            records,
+            vec![vec!["a", "b,c", "say \"hi\""], vec!["multi\nline", "", "x"]]
+        );
+        assert!(parse_csv("\"unterminated").is_err());
+    }
+
+    #[test]
+    fn test_parse_keepass_export() {
+        let import = parse(concat!(
+            "\"Group\",\"Title\",\"Username\",\"Password\",\"URL\",\"Notes\",\"TOTP\"\n",
+            "\"Root\",\"GitHub\",\"john\",\"p,ss\",\"https://github.com\",\"\",\"\"\n",
+            "\"Root/work\",\"aws\",\"admin\",\"secret\",\"\",\"line 1\nline 2\",\"x\"\n",
+            "\"Root\",\"\",\"nobody\",\"\",\"\",\"\",\"\"\n",
+            "\"Root\",\"short\"\n",
+        ))
+        .unwrap();
+
+        let labels: Vec<&str> = import.secrets.iter().map(|(l, _)| l.as_str()).collect();
+        assert_eq!(labels, vec!["GitHub", "work/aws"]);
+
+        let github = &import.secrets[0].1;
+        assert_eq!(github.get_value("password"), Some("p,ss"));
+        assert_eq!(github.get_value("url"), Some("https://github.com"));
+        assert!(!github.contains_key("note"));
+        let aws = &import.secrets[1].1;
+        assert_eq!(aws.get_value("note"), Some("line 1\nline 2"));
+        assert_eq!(aws.len(), 3);
+
+        assert_eq!(
+            import.failures,
+            vec!["Row 4: empty title", "Row 5: expected 7 columns, found 2"]
+        );
+    }
+
+    #[test]
+    fn test_parse_requires_title_column() {
+        assert!(parse("Name,Password\nGitHub,secret\n").is_err());
+        assert!(parse("").is_err());
+    }
+}
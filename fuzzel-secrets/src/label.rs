@@ -0,0 +1,240 @@
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use url::{Host, Url};
+
+/// Second-level labels that are commonly part of a public suffix, like `co.uk`
+const COMMON_SECOND_LEVELS: &[&str] = &["ac", "co", "com", "edu", "gov", "net", "org"];
+
+/// Compare labels paired with their field count: most fields first, then by label
+pub fn compare_field_counts(a: (&str, usize), b: (&str, usize)) -> Ordering {
+    b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0))
+}
+
+/// Separator of namespaces in labels like `work/aws`
+pub const NAMESPACE_SEPARATOR: char = '/';
+
+/// Put a label below a namespace, unless it already is
+pub fn with_prefix(prefix: &str, label: &str) -> String {
+    let prefix = prefix.trim_end_matches(NAMESPACE_SEPARATOR);
+    if prefix.is_empty() {
+        return label.to_string();
+    }
+    match label.strip_prefix(prefix) {
+        Some(rest) if rest.starts_with(NAMESPACE_SEPARATOR) => label.to_string(),
+        _ => format!("{}{}{}", prefix, NAMESPACE_SEPARATOR, label),
+    }
+}
+
+/// A line of a label tree, either a namespace heading or a label
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TreeLine {
+    /// Indented text to display
+    pub text: String,
+    /// Full namespace ending with the separator for headings, the label otherwise
+    pub path: String,
+    pub namespace: bool,
+}
+
+/// Lay out labels as a tree, grouping them by namespace
+///
+/// `work/aws` and `work/gitlab` are shown as a `work/` heading followed by
+/// the indented `aws` and `gitlab` lines.
+pub fn tree(labels: &[String]) -> Vec<TreeLine> {
+    let mut labels: Vec<Vec<&str>> = labels
+        .iter()
+        .map(|label| label.split(NAMESPACE_SEPARATOR).collect())
+        .collect();
+    labels.sort();
+
+    let mut lines = Vec::new();
+    let mut previous: &[&str] = &[];
+    for segments in &labels {
+        let (name, namespace) = segments.split_last().expect("split yields a segment");
+        let common = previous
+            .iter()
+            .zip(namespace.iter())
+            .take_while(|(a, b)| a == b)
+            .count();
+
+        for depth in common..namespace.len() {
+            let mut path = namespace[..=depth].join(&NAMESPACE_SEPARATOR.to_string());
+            path.push(NAMESPACE_SEPARATOR);
+            lines.push(TreeLine {
+                text: format!(
+                    "{}{}{}",
+                    "  ".repeat(depth),
+                    namespace[depth],
+                    NAMESPACE_SEPARATOR
+                ),
+                path,
+                namespace: true,
+            });
+        }
+        lines.push(TreeLine {
+            text: format!("{}{}", "  ".repeat(namespace.len()), name),
+            path: segments.join(&NAMESPACE_SEPARATOR.to_string()),
+            namespace: false,
+        });
+        previous = namespace;
+    }
+
+    lines
+}
+
+/// Suggest a label for a URL from its registrable domain
+///
+/// `https://www.github.com:443/login` becomes `github.com`. URLs without a
+/// scheme are accepted. Without a public suffix list, the registrable domain
+/// is guessed as the last two labels, or three for suffixes like `co.uk`.
+pub fn suggest_from_url(url: &str) -> Option<String> {
+    let url = url.trim();
+    let parsed = if url.contains("://") {
+        Url::parse(url)
+    } else {
+        Url::parse(&format!("https://{}", url))
+    }
+    .ok()?;
+
+    let domain = match parsed.host()? {
+        Host::Domain(domain) => domain.to_lowercase(),
+        Host::Ipv4(ip) => return Some(ip.to_string()),
+        Host::Ipv6(ip) => return Some(ip.to_string()),
+    };
+
+    let labels: Vec<&str> = domain
+        .trim_end_matches('.')
+        .split('.')
+        .filter(|l| !l.is_empty())
+        .collect();
+
+    let keep = match labels.as_slice() {
+        [.., second, tld] if tld.len() == 2 && COMMON_SECOND_LEVELS.contains(second) => 3,
+        _ => 2,
+    };
+
+    Some(labels[labels.len().saturating_sub(keep)..].join("."))
+}
+
+/// Normalize a label for comparison, ignoring case and whitespace differences
+pub fn dedupe_key(label: &str) -> String {
+    label
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+/// Group labels that only differ by case or whitespace
+///
+/// Only groups with more than one label are returned, each sorted.
+pub fn duplicate_groups(labels: &[String]) -> Vec<Vec<String>> {
+    let mut groups: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for label in labels {
+        groups
+            .entry(dedupe_key(label))
+            .or_default()
+            .push(label.clone());
+    }
+
+    groups
+        .into_values()
+        .filter(|group| group.len() > 1)
+        .map(|mut group| {
+            group.sort();
+            group
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compare_field_counts() {
+        let mut labels = vec![("b", 2), ("c", 5), ("a", 2)];
+        labels.sort_by(|a, b| compare_field_counts(*a, *b));
+        assert_eq!(labels, vec![("c", 5), ("a", 2), ("b", 2)]);
+    }
+
+    #[test]
+    fn test_with_prefix() {
+        assert_eq!(with_prefix("work", "aws"), "work/aws");
+        assert_eq!(with_prefix("work/", "aws"), "work/aws");
+        assert_eq!(with_prefix("work", "work/aws"), "work/aws");
+        assert_eq!(with_prefix("work", "workshop"), "work/workshop");
+        assert_eq!(with_prefix("", "aws"), "aws");
+    }
+
+    #[test]
+    fn test_tree() {
+        let labels: Vec<String> = ["work/gitlab", "github", "work/aws/prod", "work/aws/dev"]
+            .map(String::from)
+            .to_vec();
+        let lines = tree(&labels);
+
+        let texts: Vec<&str> = lines.iter().map(|l| l.text.as_str()).collect();
+        assert_eq!(
+            texts,
+            vec!["github", "work/", "  aws/", "    dev", "    prod", "  gitlab"]
+        );
+        assert_eq!(lines[2].path, "work/aws/");
+        assert!(lines[2].namespace);
+        assert_eq!(lines[3].path, "work/aws/dev");
+        assert!(!lines[3].namespace);
+    }
+
+    #[test]
+    fn test_suggest_from_url() {
+        assert_eq!(
+            suggest_from_url("https://www.github.com/login").as_deref(),
+            Some("github.com")
+        );
+        assert_eq!(
+            suggest_from_url("accounts.google.com").as_deref(),
+            Some("google.com")
+        );
+        assert_eq!(
+            suggest_from_url("login.example.co.uk:8443/x?y=z").as_deref(),
+            Some("example.co.uk")
+        );
+        assert_eq!(
+            suggest_from_url("http://192.168.1.1:8080").as_deref(),
+            Some("192.168.1.1")
+        );
+        assert_eq!(
+            suggest_from_url("localhost:3000").as_deref(),
+            Some("localhost")
+        );
+    }
+
+    #[test]
+    fn test_duplicate_groups() {
+        let labels: Vec<String> = [
+            "GitHub",
+            "github ",
+            "Github",
+            "GitLab",
+            "work  mail",
+            "Work mail",
+        ]
+        .iter()
+        .map(|l| l.to_string())
+        .collect();
+
+        assert_eq!(
+            duplicate_groups(&labels),
+            vec![
+                vec!["GitHub", "Github", "github "],
+                vec!["Work mail", "work  mail"],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_suggest_from_invalid_url() {
+        assert_eq!(suggest_from_url(""), None);
+        assert_eq!(suggest_from_url("not a url"), None);
+        assert_eq!(suggest_from_url("https://"), None);
+    }
+}
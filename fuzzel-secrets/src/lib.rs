@@ -1,4 +1,27 @@
+pub mod backup;
+pub mod clipboard;
+pub mod config;
+pub mod display;
+pub mod exec;
+pub mod export;
 pub mod field;
+pub mod formats;
 pub mod fuzzel;
+pub mod generator;
+pub mod history;
+pub mod keepass;
+pub mod label;
+pub mod lock;
+pub mod menu;
+pub mod notify;
+pub mod otpauth;
+pub mod pinentry;
+pub mod resolver;
 pub mod secret;
 pub mod secrets;
+pub mod serve;
+pub mod settings;
+pub mod shell;
+pub mod timestamp;
+pub mod typing;
+pub mod xdg;
@@ -0,0 +1,130 @@
+use crate::xdg;
+use anyhow::{bail, Context, Result};
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+use std::fs::{self, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+
+/// Advisory lock held while modifying a collection, released when dropped
+///
+/// The lock is a file created exclusively under `$XDG_RUNTIME_DIR`, holding the
+/// pid of its owner. A lock left behind by a process that no longer runs is
+/// taken over, so a crash never blocks storing for good.
+#[derive(Debug)]
+pub struct Lock {
+    path: PathBuf,
+}
+
+impl Lock {
+    /// Location of the lock file for a collection
+    ///
+    /// The label is percent-encoded, so any label, e.g. one containing `/`,
+    /// names a distinct file directly in the runtime directory.
+    pub fn path(collection: &str) -> PathBuf {
+        let name = utf8_percent_encode(collection, NON_ALPHANUMERIC);
+        xdg::runtime_dir().join(format!("fuzzel-secrets-{}.lock", name))
+    }
+
+    /// Take the lock for a collection, failing fast if another process holds it
+    pub fn acquire(collection: &str) -> Result<Lock> {
+        Self::acquire_at(Self::path(collection))
+    }
+
+    fn acquire_at(path: PathBuf) -> Result<Lock> {
+        match create(&path) {
+            Err(err) if err.kind() == ErrorKind::AlreadyExists => {
+                if let Some(pid) = owner(&path).filter(|pid| is_running(*pid)) {
+                    bail!(
+                        "Another fuzzel-secrets process (pid {}) is modifying the collection; remove {} if it is stale",
+                        pid,
+                        path.display()
+                    );
+                }
+                // Stale lock of a process that didn't clean up
+                fs::remove_file(&path)
+                    .with_context(|| format!("Failed to remove stale lock {}", path.display()))?;
+                create(&path)
+            }
+            result => result,
+        }
+        .with_context(|| format!("Failed to create lock {}", path.display()))?;
+
+        Ok(Lock { path })
+    }
+}
+
+impl Drop for Lock {
+    fn drop(&mut self) {
+        if let Err(err) = fs::remove_file(&self.path) {
+            eprintln!("Failed to remove lock {}: {}", self.path.display(), err);
+        }
+    }
+}
+
+/// Exclusively create the lock file and record our pid in it
+fn create(path: &Path) -> std::io::Result<()> {
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(path)?;
+    write!(file, "{}", std::process::id())
+}
+
+fn owner(path: &Path) -> Option<u32> {
+    fs::read_to_string(path).ok()?.trim().parse().ok()
+}
+
+fn is_running(pid: u32) -> bool {
+    Path::new("/proc").join(pid.to_string()).exists()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lock_path(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "fuzzel-secrets-{}-{}.lock",
+            name,
+            std::process::id()
+        ));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn test_path_escapes_collection_label() {
+        let path = Lock::path("../work/keys");
+        assert_eq!(path.parent(), Some(xdg::runtime_dir().as_path()));
+        assert_eq!(
+            path.file_name().unwrap(),
+            "fuzzel-secrets-%2E%2E%2Fwork%2Fkeys.lock"
+        );
+        assert_ne!(Lock::path("a/b"), Lock::path("a_b"));
+    }
+
+    #[test]
+    fn test_second_acquire_fails_until_released() {
+        let path = lock_path("lock");
+
+        let lock = Lock::acquire_at(path.clone()).unwrap();
+        assert!(Lock::acquire_at(path.clone()).is_err());
+
+        drop(lock);
+        assert!(!path.exists());
+        assert!(Lock::acquire_at(path).is_ok());
+    }
+
+    #[test]
+    fn test_stale_lock_is_taken_over() {
+        let path = lock_path("stale");
+        // Pids are capped well below this, so no process can own it
+        fs::write(&path, "999999999").unwrap();
+
+        let lock = Lock::acquire_at(path.clone()).unwrap();
+        assert_eq!(owner(&path), Some(std::process::id()));
+        drop(lock);
+    }
+}
@@ -1,7 +1,36 @@
 use anyhow::{Context, Result};
-use clap::{Parser, Subcommand};
-use fuzzel_secrets::{field::Field, fuzzel, secret::Secret, secrets};
-use std::process::Command;
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use futures::future::try_join_all;
+use fuzzel_secrets::{
+    backup,
+    clipboard::{self, ClipTool},
+    config::{Config, OnCancel, PasswordSource},
+    display, exec,
+    export::{self, Export},
+    field::{self, Field},
+    fuzzel, generator,
+    history::History,
+    keepass, label,
+    lock::Lock,
+    menu::{self, MenuAction},
+    notify, otpauth, pinentry, resolver,
+    secret::Secret,
+    secrets::{self, NotFound, SecretError, UnlockStatus},
+    serve,
+    settings::{self, Settings},
+    shell, timestamp,
+    typing::{self, TypeMode},
+};
+use std::collections::HashMap;
+use std::io::Write;
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitCode};
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::UnixStream;
+use tokio::task::JoinHandle;
 
 #[derive(Parser)]
 #[command(name = "fuzzel-secrets")]
@@ -9,151 +38,1989 @@ use std::process::Command;
 struct Cli {
     #[command(subcommand)]
     command: Commands,
+    /// Fail instead of prompting when the collection is locked
+    #[arg(long, global = true)]
+    no_auto_unlock: bool,
+    /// Show a desktop notification when a secret is stored or retrieved
+    #[arg(long, global = true)]
+    notify: bool,
+    /// Decode secret payloads that aren't valid UTF-8 lossily instead of failing
+    #[arg(long, global = true)]
+    lenient: bool,
+    /// fuzzel configuration file to use for all prompts, e.g. for a distinct theme
+    #[arg(long, global = true)]
+    fuzzel_config: Option<PathBuf>,
+    /// Label or alias of the collection holding the secrets [env: FUZZEL_SECRETS_COLLECTION]
+    #[arg(long, global = true)]
+    collection: Option<String>,
+    /// Width of fuzzel windows in characters, e.g. for long labels
+    #[arg(long, global = true, value_name = "CHARS", value_parser = clap::value_parser!(u32).range(1..))]
+    width: Option<u32>,
+    /// Fail instead of showing any prompt, e.g. in scripts and CI
+    #[arg(long, global = true)]
+    non_interactive: bool,
 }
 
 #[derive(Subcommand)]
 enum Commands {
     /// Store a new secret
-    Store,
+    Store {
+        /// Mask every field value while typing, not just sensitive ones
+        #[arg(long)]
+        mask_all_inputs: bool,
+        /// Website of the secret, stored as the `url` field and used to suggest a label
+        #[arg(long)]
+        url: Option<String>,
+        /// RFC 3339 time after which the secret should be rotated, e.g. 2025-01-31T00:00:00Z
+        #[arg(long)]
+        expires_at: Option<String>,
+        /// Namespace to store the secret under, e.g. `work` for `work/aws`
+        #[arg(long)]
+        prefix: Option<String>,
+        /// Back up the previous contents of the secret before overwriting it
+        #[arg(long)]
+        backup: bool,
+        /// What cancelling a field prompt does
+        #[arg(long, value_enum)]
+        on_cancel: Option<OnCancel>,
+        /// Don't suggest field names used by other secrets, which decrypts all of them
+        #[arg(long)]
+        no_key_suggestions: bool,
+        /// Sort the fields in the menu, or with `false` keep the order they were added in
+        #[arg(long, value_name = "BOOL")]
+        sort_fields: Option<bool>,
+        /// Print the payload that would be stored to stderr instead of storing it,
+        /// without connecting to the Secret Service
+        #[arg(long, conflicts_with = "backup")]
+        dry_run: bool,
+    },
     /// Retrieve and type a secret
-    Retrieve,
+    Retrieve(RetrieveArgs),
+    /// Print the value of a field to stdout, without prompting
+    Print {
+        /// Label of the secret
+        label: String,
+        /// Field to print
+        field: String,
+        /// Print the value byte for byte, without appending a newline
+        #[arg(long)]
+        raw: bool,
+    },
+    /// Copy the value of a field to the clipboard, without prompting
+    Copy {
+        /// Label of the secret
+        #[arg(long)]
+        label: String,
+        /// Field to copy
+        #[arg(long)]
+        field: String,
+        /// Clipboard tool to use
+        #[arg(long, value_enum)]
+        clip_tool: Option<ClipTool>,
+        /// Copy the value byte for byte, keeping a trailing line break
+        #[arg(long)]
+        no_trim: bool,
+    },
+    /// Show the fields of a secret, masking sensitive values
+    Show {
+        /// Label of the secret, selected with fuzzel if omitted
+        label: Option<String>,
+        /// Briefly show the real values in fuzzel instead of printing masked values
+        #[arg(long)]
+        reveal: bool,
+        /// Seconds before revealed values are hidden again
+        #[arg(long, requires = "reveal")]
+        reveal_timeout: Option<u64>,
+        /// How to lay out the fields
+        #[arg(long, value_enum, default_value_t = ShowFormat::List)]
+        output: ShowFormat,
+        /// Also print the unencrypted Secret Service attributes of every item with the label
+        #[arg(long)]
+        attributes: bool,
+    },
+    /// Export all secrets as JSON, or as a pass store with `--format pass`
+    Export {
+        /// File to write to instead of stdout, or the directory for `--format pass`
+        #[arg(long, required_if_eq("format", "pass"))]
+        output: Option<PathBuf>,
+        /// Format to export in
+        #[arg(long, value_enum, default_value_t = ExportFormat::Json)]
+        format: ExportFormat,
+        /// Leave out sensitive fields, keeping a shareable skeleton
+        #[arg(long)]
+        no_sensitive: bool,
+        /// Only export these fields, e.g. `username,url`
+        #[arg(long, value_delimiter = ',')]
+        only: Option<Vec<String>>,
+    },
+    /// Copy a secret under a new label
+    Duplicate {
+        /// Edit the fields of the copy before storing it
+        #[arg(long)]
+        edit: bool,
+    },
+    /// Run a command with the fields of a secret as environment variables
+    Exec {
+        /// Label of the secret, selected with fuzzel if omitted
+        #[arg(long)]
+        label: Option<String>,
+        /// Command and arguments to run
+        #[arg(last = true, required = true)]
+        command: Vec<String>,
+    },
+    /// Unlock the collection holding the secrets
+    Unlock,
+    /// List all Secret Service collections, marking locked ones
+    Collections,
+    /// List the labels of all secrets
+    List {
+        /// Show groups of labels that only differ by case or whitespace
+        #[arg(long)]
+        suggest_dedupe: bool,
+        /// Only show secrets past their expiry (slower, decrypts all secrets)
+        #[arg(long, conflicts_with = "suggest_dedupe")]
+        expired: bool,
+        /// Show the labels as a tree of their `/`-separated namespaces
+        #[arg(long, conflicts_with_all = ["suggest_dedupe", "expired"])]
+        tree: bool,
+        /// Order of the labels
+        #[arg(long, value_enum, default_value_t = ListSort::Alpha, conflicts_with = "tree")]
+        sort: ListSort,
+    },
+    /// Delete secrets
+    Delete {
+        /// Label of the secret, selected with fuzzel if omitted
+        label: Option<String>,
+        /// Select several secrets to delete at once
+        #[arg(long, conflicts_with = "label")]
+        multi: bool,
+    },
+    /// Replace a sensitive field with a generated value, keeping the old one for undo
+    Rotate {
+        /// Label of the secret, selected with fuzzel if omitted
+        label: Option<String>,
+        /// Field to rotate, selected with fuzzel if omitted
+        #[arg(long)]
+        field: Option<String>,
+        /// Restore the value from before the last rotation instead
+        #[arg(long)]
+        undo: bool,
+        /// Copy the new value to the clipboard instead of typing it
+        #[arg(long, conflicts_with = "undo")]
+        clipboard: bool,
+    },
+    /// Merge secrets whose labels only differ by case or whitespace
+    Dedupe,
+    /// Delete the collection holding the secrets and every secret in it
+    Purge,
+    /// Keep a Secret Service connection open and handle requests sent to a socket
+    Serve,
+    /// Pick an item with fuzzel and print it, like dmenu; defaults to the secret labels
+    Select {
+        /// Items to choose from instead of the secret labels
+        items: Vec<String>,
+        /// Read the items from stdin, one per line
+        #[arg(long, conflicts_with = "items")]
+        stdin: bool,
+        /// Placeholder shown in fuzzel
+        #[arg(long)]
+        placeholder: Option<String>,
+    },
+    /// Import secrets stored by other tools
+    Import {
+        /// Import the items of this schema from the default collection, e.g. from secret-tool
+        #[arg(long, required_unless_present = "format", conflicts_with = "format")]
+        from_schema: Option<String>,
+        /// Format of the file to import
+        #[arg(long, value_enum, requires = "input")]
+        format: Option<ImportFormat>,
+        /// File, or directory for `--format pass`, to import with --format
+        #[arg(long)]
+        input: Option<PathBuf>,
+    },
+    /// List the attribute keys of all secrets, or the distinct values of one
+    Tags {
+        /// Attribute to list the values of, e.g. `category`
+        #[arg(long)]
+        key: Option<String>,
+    },
+    /// Check that every stored secret can be decoded, without modifying anything
+    Check,
+    /// Store the secrets of a backup or export file, asking before overwriting
+    Restore {
+        /// Backup or export file to restore
+        #[arg(long, required_unless_present = "list", conflicts_with = "list")]
+        input: Option<PathBuf>,
+        /// List the automatic backups instead, oldest first
+        #[arg(long)]
+        list: bool,
+    },
 }
 
-async fn retrieve() -> Result<()> {
-    let mut all_secrets = secrets::secrets()
-        .await
-        .context("Failed to retrieve secrets")?;
-    all_secrets.sort();
-    let all_secrets = all_secrets;
+/// Formats `export` writes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum ExportFormat {
+    /// A JSON object of secrets keyed by label, as read by `restore`
+    Json,
+    /// A directory of `pass`-style entries, one `<label>.txt` file per secret
+    Pass,
+}
+
+/// File formats `import` reads
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum ImportFormat {
+    /// CSV export of KeePassXC
+    KeepassCsv,
+    /// A directory of `pass`-style entries, as written by `export --format pass`
+    Pass,
+}
+
+/// Order of the labels printed by `list`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum ListSort {
+    /// Alphabetically
+    Alpha,
+    /// Most recently retrieved first, see `remember_fields`
+    Recent,
+    /// Most fields first (slower, decrypts all secrets)
+    Fields,
+}
+
+/// Layout of the fields printed by `show`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum ShowFormat {
+    /// One "key: value" line per field
+    List,
+    /// Keys and values aligned in columns
+    Table,
+}
+
+/// A request sent to `serve`, one line per connection, e.g. `retrieve --enter`
+///
+/// Arguments are split on whitespace, so labels with spaces can't be passed.
+#[derive(Parser)]
+#[command(name = "request", no_binary_name = true)]
+enum ServeRequest {
+    /// Retrieve and type a secret
+    Retrieve(RetrieveArgs),
+    /// Store a new secret
+    Store {
+        /// Website of the secret
+        #[arg(long)]
+        url: Option<String>,
+    },
+}
+
+#[derive(Args)]
+struct RetrieveArgs {
+    /// Label of the secret, skipping the label picker
+    #[arg(long)]
+    label: Option<String>,
+    /// Field to retrieve, skipping the field picker
+    #[arg(long)]
+    field: Option<String>,
+    /// Show the number of fields of each secret in the picker (slower, decrypts all secrets)
+    #[arg(long)]
+    count_fields: bool,
+    /// Press Enter after typing the value
+    #[arg(long, conflicts_with = "clipboard")]
+    enter: bool,
+    /// Copy the value to the clipboard instead of typing it
+    #[arg(long)]
+    clipboard: bool,
+    /// Clipboard tool to use with --clipboard
+    #[arg(long, value_enum)]
+    clip_tool: Option<ClipTool>,
+    /// Copy the value byte for byte with --clipboard, keeping a trailing line break
+    #[arg(long, requires = "clipboard")]
+    no_trim: bool,
+    /// Show the icon named by each secret's `icon` field in the picker
+    #[arg(long)]
+    icon_from_attribute: bool,
+    /// List only field keys in the field picker, or with `false` their values too
+    #[arg(long, value_name = "BOOL")]
+    mask_in_picker: Option<bool>,
+    /// Type values as text, or as a keysym per character for layouts wtype mistypes
+    #[arg(long, value_enum, conflicts_with = "clipboard")]
+    type_mode: Option<TypeMode>,
+    /// Offer at most this many secrets in the picker, most recently retrieved first
+    #[arg(long, value_name = "N")]
+    limit: Option<usize>,
+}
+
+/// Pick a label from a tree of namespaces, narrowing down to a namespace when
+/// its heading is picked
+fn select_grouped(labels: &[String], placeholder: &str) -> Result<String> {
+    let mut labels = labels.to_vec();
+    loop {
+        let lines = label::tree(&labels);
+        let texts: Vec<String> = lines.iter().map(|line| line.text.clone()).collect();
+        let index =
+            fuzzel::select_index(&texts, Some(placeholder)).context("Failed to select secret")?;
+        let line = lines
+            .get(index)
+            .ok_or_else(|| anyhow::anyhow!("Invalid index returned from fuzzel"))?;
+
+        if !line.namespace {
+            return Ok(line.path.clone());
+        }
+        labels.retain(|label| label.starts_with(&line.path));
+    }
+}
+
+async fn retrieve(config: &Config, args: RetrieveArgs) -> Result<()> {
+    let mut history = if config.remember_fields {
+        History::load().unwrap_or_else(|err| {
+            eprintln!("Ignoring history: {:#}", err);
+            History::default()
+        })
+    } else {
+        History::default()
+    };
+
+    let requested_secret = match args.label {
+        Some(label) => label,
+        None => {
+            let mut all_secrets = secrets::secrets()
+                .await
+                .context("Failed to retrieve secrets")?;
+            all_secrets.sort();
+            let total = all_secrets.len();
+            if let Some(limit) = args.limit {
+                // Keep the most relevant secrets rather than the first ones alphabetically
+                all_secrets.sort_by(|a, b| history.compare_recent(a, b));
+                all_secrets.truncate(limit);
+            }
+            let all_secrets = all_secrets;
+            let placeholder = if all_secrets.len() < total {
+                format!("Select secret ({} of {})", all_secrets.len(), total)
+            } else {
+                "Select secret".to_string()
+            };
+
+            if config.group_labels && !args.count_fields {
+                select_grouped(&all_secrets, &placeholder)?
+            } else {
+                let items = if args.count_fields {
+                    // Decrypts every secret, hence opt-in
+                    let counts = try_join_all(all_secrets.iter().map(|label| async move {
+                        secrets::get_data(label).await.map(|data| data.len())
+                    }))
+                    .await
+                    .context("Failed to count fields")?;
 
-    let requested_secret =
-        fuzzel::select(&all_secrets, Some("Select secret")).context("Failed to select secret")?;
+                    all_secrets
+                        .iter()
+                        .zip(counts)
+                        .map(|(label, count)| match count {
+                            1 => format!("{} (1 field)", label),
+                            _ => format!("{} ({} fields)", label, count),
+                        })
+                        .collect()
+                } else {
+                    all_secrets.clone()
+                };
+
+                let items = if args.icon_from_attribute {
+                    // Icons are read from the unencrypted attributes; an
+                    // unreadable item only loses its own icon
+                    let icons: HashMap<String, String> = secrets::read_summaries()
+                        .await
+                        .and_then(secrets::Partial::readable)
+                        .context("Failed to retrieve secrets")?
+                        .into_iter()
+                        .filter_map(|summary| {
+                            let icon = summary.icon()?.to_string();
+                            Some((summary.label, icon))
+                        })
+                        .collect();
+                    items
+                        .into_iter()
+                        .zip(&all_secrets)
+                        .map(|(item, label)| match icons.get(label) {
+                            Some(icon) => fuzzel::with_icon(&item, icon),
+                            None => item,
+                        })
+                        .collect()
+                } else {
+                    items
+                };
+
+                // Select by index so annotations never leak into the label
+                let index = fuzzel::select_index(&items, Some(&placeholder))
+                    .context("Failed to select secret")?;
+                all_secrets
+                    .get(index)
+                    .cloned()
+                    .ok_or_else(|| anyhow::anyhow!("Invalid index returned from fuzzel"))?
+            }
+        }
+    };
 
-    let data = secrets::get_data(&requested_secret)
+    let data = secrets::select_data(&requested_secret)
         .await
-        .context("Failed to get secret data")?;
+        .with_context(|| format!("Failed to get secret data for '{}'", requested_secret))?;
 
     if data.is_empty() {
-        return Err(anyhow::anyhow!("No fields found in secret"));
+        return Err(SecretError::Empty(requested_secret).into());
     }
 
-    let fields: Vec<String> = data.keys().cloned().collect();
-    let field = fuzzel::select(&fields, Some("Field")).context("Failed to select field")?;
+    if let Some(expires_at) = data.expires_at() {
+        let warning_seconds = config.expiry_warning_days.saturating_mul(86400) as i64;
+        if expires_at - timestamp::now() <= warning_seconds {
+            let verb = if expires_at <= timestamp::now() {
+                "expired"
+            } else {
+                "expires"
+            };
+            let warning = format!(
+                "{} {} {}",
+                requested_secret,
+                verb,
+                timestamp::format_rfc3339(expires_at)
+            );
+            if config.notify {
+                notify::send(&warning);
+            } else if !settings::with(|settings| settings.interactive) {
+                eprintln!("{}", warning);
+            } else if let Err(err) = fuzzel::message(
+                std::slice::from_ref(&warning),
+                Some("Expiry"),
+                Some(Duration::from_secs(3)),
+            ) {
+                // A warning that can't be shown must not keep scripts from
+                // retrieving the value
+                eprintln!("{}", warning);
+                let expected = err.chain().any(|cause| {
+                    cause.is::<fuzzel::NotInteractive>()
+                        || cause.is::<display::NoDisplay>()
+                        || cause.is::<fuzzel::Cancelled>()
+                });
+                if !expected {
+                    eprintln!("Failed to show expiry warning: {:#}", err);
+                }
+            }
+        }
+    }
+
+    let field = match args.field {
+        Some(field) => field,
+        None => {
+            let mut fields: Vec<String> = data.keys().cloned().collect();
+            fields.sort_by(|a, b| field::compare_keys(&config.field_order, a, b));
+            history.order_fields(&requested_secret, &mut fields);
+            let items = picker_fields(
+                &data,
+                &fields,
+                args.mask_in_picker.unwrap_or(config.mask_in_picker),
+            );
+            let index =
+                fuzzel::select_index(&items, Some("Field")).context("Failed to select field")?;
+            fields
+                .get(index)
+                .cloned()
+                .ok_or_else(|| anyhow::anyhow!("Invalid index returned from fuzzel"))?
+        }
+    };
 
     let value = data
-        .get(&field)
-        .ok_or_else(|| anyhow::anyhow!("Field not found"))?;
+        .get_value(&field)
+        .ok_or_else(|| NotFound::Field(field.clone()))?;
+    let resolved = resolve_value(config, &requested_secret, &field, value).await?;
+    let value = &resolved.value;
 
-    Command::new("wtype")
-        .arg("--")
-        .arg(&value.value)
-        .status()
-        .context("Failed to execute wtype")?;
+    if args.clipboard {
+        copy_value(
+            config,
+            &requested_secret,
+            &field,
+            data.is_sensitive(&field) || resolved.sensitive,
+            value,
+        )
+        .await
+        .with_context(|| format!("Failed to copy '{}' of '{}'", field, requested_secret))?;
+    } else {
+        type_value(config, value)
+            .with_context(|| format!("Failed to type '{}' of '{}'", field, requested_secret))?;
+        if args.enter || data.submits(&field) {
+            typing::press_enter()?;
+        }
+        if config.notify {
+            notify::send(&format!("Typed {} for {}", field, requested_secret));
+        }
+    }
+
+    if config.remember_fields {
+        history.record_field(&requested_secret, &field);
+        if let Err(err) = history.save() {
+            eprintln!("Failed to save history: {:#}", err);
+        }
+    }
 
     Ok(())
 }
 
-async fn store() -> Result<()> {
-    // Start fetching existing keys concurrently, it takes some time
-    let existing_keys_task = tokio::spawn(async move { secrets::all_field_keys().await });
+/// Items of the retrieve field picker: the keys, or with `mask` disabled the
+/// keys with their real values, e.g. `username: john`
+fn picker_fields(data: &Secret, keys: &[String], mask: bool) -> Vec<String> {
+    keys.iter()
+        .map(|key| match data.get_value(key) {
+            // Each item has to stay on one line to be selected by index
+            Some(value) if !mask => format!("{}: {}", key, value.replace('\n', " ")),
+            _ => key.clone(),
+        })
+        .collect()
+}
 
-    let mut all_secrets = secrets::secrets()
-        .await
-        .context("Failed to retrieve secrets")?;
-    all_secrets.sort();
-    let all_secrets = all_secrets;
+/// The value a field stands for, following references to other secrets
+async fn resolve_value(
+    config: &Config,
+    label: &str,
+    key: &str,
+    value: &str,
+) -> Result<resolver::Resolved> {
+    resolver::resolve(value, config.max_reference_depth, |label| async move {
+        secrets::get_data(&label).await
+    })
+    .await
+    .with_context(|| format!("Failed to resolve '{}' of '{}'", key, label))
+}
 
-    let requested_secret = fuzzel::select_or_input(&all_secrets, Some("Label"))
-        .context("Failed to get service name")?;
+/// Type a value with the configured type command, or wtype
+fn type_value(config: &Config, value: &str) -> Result<()> {
+    match (&config.type_command, config.type_mode) {
+        (Some(template), _) => typing::type_with_command(template, value),
+        (None, TypeMode::Text) => typing::type_text(value),
+        (None, TypeMode::Keysym) => typing::type_keysyms(value),
+    }
+}
 
-    // Check if service already exists and load its data
-    let existing_secret = all_secrets
-        .iter()
-        .find(|secret| secret.to_string() == requested_secret);
+/// Clipboard clears scheduled by [`copy_value`], waited for before exiting
+static PENDING_CLEARS: Mutex<Vec<JoinHandle<Result<()>>>> = Mutex::new(Vec::new());
 
-    let mut data = match existing_secret {
-        Some(secret) => secrets::get_data(secret)
+/// Wait until every scheduled clipboard clear has run
+async fn finish_clipboard_clears() -> Result<()> {
+    let pending = std::mem::take(&mut *PENDING_CLEARS.lock().unwrap());
+    for clear in pending {
+        clear
             .await
-            .context("Failed to get existing secret data")?,
-        None => Secret::new(),
+            .context("Failed to join clipboard clear task")??;
+    }
+    Ok(())
+}
+
+/// Copy a value to the clipboard, clearing it again after a while if it is sensitive
+///
+/// The clear runs as a task of its own, so `serve` keeps answering requests
+/// meanwhile; commands wait for it with [`finish_clipboard_clears`].
+async fn copy_value(
+    config: &Config,
+    label: &str,
+    key: &str,
+    sensitive: bool,
+    value: &str,
+) -> Result<()> {
+    clipboard::copy(config.clip_tool, value, config.clipboard_trim)
+        .context("Failed to copy to clipboard")?;
+    if config.notify {
+        notify::send(&format!("Copied {} for {}", key, label));
+    }
+
+    // Don't leave sensitive values on the clipboard indefinitely
+    if sensitive && config.clipboard_clear_seconds > 0 {
+        let tool = config.clip_tool;
+        let delay = Duration::from_secs(config.clipboard_clear_seconds);
+        let clear = tokio::spawn(async move {
+            tokio::time::sleep(delay).await;
+            clipboard::clear(tool).context("Failed to clear clipboard")
+        });
+        let mut pending = PENDING_CLEARS.lock().unwrap();
+        pending.retain(|clear| !clear.is_finished());
+        pending.push(clear);
+    }
+
+    Ok(())
+}
+
+/// Print the value followed by a newline, or exactly the value with `raw`
+async fn print(config: &Config, label: &str, field: &str, raw: bool) -> Result<()> {
+    let data = secrets::get_data(label)
+        .await
+        .with_context(|| format!("Failed to get secret data for '{}'", label))?;
+    let value = data
+        .get_value(field)
+        .ok_or_else(|| NotFound::Field(field.to_string()))?;
+    let value = resolve_value(config, label, field, value).await?.value;
+
+    let mut stdout = std::io::stdout().lock();
+    stdout
+        .write_all(value.as_bytes())
+        .and_then(|()| if raw { Ok(()) } else { stdout.write_all(b"\n") })
+        .and_then(|()| stdout.flush())
+        .context("Failed to write value to stdout")
+}
+
+/// Copy a field to the clipboard like `retrieve --clipboard`, skipping both pickers
+async fn copy(config: &Config, label: &str, field: &str) -> Result<()> {
+    let data = secrets::get_data(label)
+        .await
+        .with_context(|| format!("Failed to get secret data for '{}'", label))?;
+    let value = data
+        .get_value(field)
+        .ok_or_else(|| NotFound::Field(field.to_string()))?;
+    let resolved = resolve_value(config, label, field, value).await?;
+    let sensitive = data.is_sensitive(field) || resolved.sensitive;
+
+    copy_value(config, label, field, sensitive, &resolved.value).await
+}
+
+async fn show(
+    config: &Config,
+    label: Option<String>,
+    reveal: bool,
+    reveal_timeout: Option<u64>,
+    output: ShowFormat,
+    attributes: bool,
+) -> Result<()> {
+    let label = match label {
+        Some(label) => label,
+        None => {
+            let mut all_secrets = secrets::secrets()
+                .await
+                .context("Failed to retrieve secrets")?;
+            all_secrets.sort();
+            fuzzel::select(&all_secrets, Some("Select secret"))
+                .context("Failed to select secret")?
+        }
     };
 
-    let mut existing_keys = existing_keys_task
+    let data = secrets::select_data(&label)
         .await
-        .context("Failed to join existing keys task")?
-        .context("Failed to retrieve existing field keys")?;
-    existing_keys.sort();
-    let existing_keys = existing_keys;
+        .with_context(|| format!("Failed to get secret data for '{}'", label))?;
+
+    let mut fields = data.fields();
+    fields.sort_by(|a, b| field::compare_keys(&config.field_order, &a.key, &b.key));
+
+    let lines: Vec<String> = match output {
+        ShowFormat::List if reveal => fields
+            .iter()
+            .map(|f| format!("{}: {}", f.key, f.value))
+            .collect(),
+        ShowFormat::List => fields.iter().map(|f| f.to_string()).collect(),
+        ShowFormat::Table => field::table(&fields, reveal),
+    };
+
+    if reveal {
+        // Revealed values only go to a transient fuzzel window, never to stdout
+        let timeout = reveal_timeout.unwrap_or(config.reveal_timeout_seconds);
+        fuzzel::message(&lines, Some(&label), Some(Duration::from_secs(timeout)))
+            .context("Failed to show secret")?;
+    } else {
+        for line in lines {
+            println!("{}", line);
+        }
+    }
+
+    if attributes {
+        // Attributes aren't secret, so they are printed even with --reveal
+        let items = secrets::attributes(&label)
+            .await
+            .with_context(|| format!("Failed to get attributes of '{}'", label))?;
+        for (i, item) in items.iter().enumerate() {
+            // Set apart from the fields printed above
+            if !reveal || i > 0 {
+                println!();
+            }
+            match items.len() {
+                1 => println!("Attributes:"),
+                count => println!("Attributes of item {} of {}:", i + 1, count),
+            }
+            for line in secrets::attribute_lines(item) {
+                println!("  {}", line);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+async fn export(
+    output: Option<PathBuf>,
+    format: ExportFormat,
+    no_sensitive: bool,
+    only: Option<Vec<String>>,
+) -> Result<()> {
+    let mut export = Export::new();
+
+    for label in secrets::secrets()
+        .await
+        .context("Failed to retrieve secrets")?
+    {
+        let mut data = secrets::get_data(&label)
+            .await
+            .with_context(|| format!("Failed to get secret data for '{}'", label))?;
+        if no_sensitive {
+            data = data.filter(|f| !f.is_sensitive());
+        }
+        if let Some(keys) = &only {
+            data.retain_keys(keys);
+        }
+        export.insert(label, data);
+    }
+
+    match (format, output) {
+        (ExportFormat::Json, Some(path)) => export::write(&path, &export)?,
+        (ExportFormat::Json, None) => println!("{}", export::to_json(&export)?),
+        (ExportFormat::Pass, Some(dir)) => {
+            let failures = export::write_pass(&dir, &export)?;
+            for failure in &failures {
+                eprintln!("Skipping {}", failure);
+            }
+            println!(
+                "Exported {} secrets to {}",
+                export.len() - failures.len(),
+                dir.display()
+            );
+        }
+        (ExportFormat::Pass, None) => unreachable!("clap requires --output with --format pass"),
+    }
 
-    let add_field_option = "+   Add field";
-    let complete_option = "✓   Complete";
+    Ok(())
+}
+
+/// Briefly show how strong a sensitive value is, without revealing it
+fn show_strength(config: &Config, key: &str, sensitive: bool, value: &str) -> Result<()> {
+    if !config.show_strength || !sensitive {
+        return Ok(());
+    }
+
+    let strength = generator::strength(value);
+    fuzzel::message(
+        &[strength.to_string()],
+        Some(&format!("Strength of '{}'", key)),
+        Some(Duration::from_secs(3)),
+    )
+    .context("Failed to show strength")
+}
+
+/// Number of times a value failing the password policy may be entered again
+const MAX_POLICY_ATTEMPTS: usize = 3;
 
+/// Ask for a value in password mode, with the configured program
+fn request_masked(config: &Config, key: &str, prompt: &str) -> Result<String> {
+    match config.password_source {
+        PasswordSource::Fuzzel => fuzzel::request_password_raw(Some(prompt)),
+        PasswordSource::Pinentry => match pinentry::request_pin(prompt, &format!("{}:", key)) {
+            Err(err) if err.is::<pinentry::NotInstalled>() => {
+                eprintln!("{}; falling back to fuzzel", err);
+                fuzzel::request_password_raw(Some(prompt))
+            }
+            result => result,
+        },
+    }
+}
+
+/// Ask for the value of a field, masking the input for sensitive fields
+///
+/// A `current` value is offered for editing in place unless the input is
+/// masked, which never shows it. A sensitive value failing the password policy
+/// can be entered again, up to [`MAX_POLICY_ATTEMPTS`] times, replaced by a
+/// generated one or stored anyway. Returns `None` if the user discards it
+/// instead.
+fn request_value(
+    config: &Config,
+    key: &str,
+    sensitive: bool,
+    current: Option<&str>,
+    prompt: &str,
+) -> Result<Option<String>> {
+    // Values are not trimmed, as surrounding whitespace may be intentional
+    if !config.mask_all_inputs && !sensitive {
+        return match current {
+            Some(current) => fuzzel::request_input_prefilled(current, Some(prompt)),
+            None => fuzzel::request_input_raw(Some(prompt)),
+        }
+        .map(Some);
+    }
+
+    let mut attempts = 0;
     loop {
-        // Build menu items
-        let mut menu_items: Vec<String> =
-            vec![add_field_option.to_string(), complete_option.to_string()];
+        attempts += 1;
+        let value = request_masked(config, key, prompt)?;
+        show_strength(config, key, sensitive, &value)?;
+
+        let violations = config.password_policy.violations(&value);
+        if !sensitive || violations.is_empty() {
+            return Ok(Some(value));
+        }
+
+        let mut choices = Vec::new();
+        if attempts < MAX_POLICY_ATTEMPTS {
+            choices.push("Re-enter");
+        }
+        choices.extend(["Generate", "Store anyway", "Discard"]);
+        let choices: Vec<String> = choices.into_iter().map(str::to_string).collect();
+
+        let question = format!("Weak '{}': {}", key, violations.join(", "));
+        let answer =
+            fuzzel::select(&choices, Some(&question)).context("Failed to confirm weak value")?;
+        match answer.as_str() {
+            "Re-enter" => continue,
+            "Generate" => {
+                let length = config
+                    .generated_length
+                    .max(config.password_policy.min_length);
+                return Ok(Some(generator::generate(length)));
+            }
+            "Store anyway" => return Ok(Some(value)),
+            _ => return Ok(None),
+        }
+    }
+}
 
-        let mut field_items: Vec<String> = data.iter().map(|f| f.display()).collect();
-        field_items.sort();
+/// Interactively add and edit fields until the user completes the secret
+///
+/// Cancelling the menu aborts the edit with a [`fuzzel::Cancelled`] error.
+/// Cancelling a prompt of a menu action returns to the menu, or aborts the
+/// edit too with [`OnCancel::Discard`].
+async fn edit_fields(
+    config: &Config,
+    mut data: Secret,
+    existing_keys: &[String],
+) -> Result<Secret> {
+    let initial_keys: Vec<String> = data.keys().cloned().collect();
 
-        menu_items.extend(field_items);
-        let menu_items = menu_items;
+    loop {
+        let menu_items = menu::items(&data, &config.field_order, config.sort_fields);
+        // Metadata isn't listed by keys(), so only user fields are counted
+        let added = data
+            .keys()
+            .filter(|key| !initial_keys.contains(key))
+            .count();
 
-        let selection = fuzzel::select(&menu_items, Some("Secret fields"))
+        let selection = fuzzel::select(&menu_items, Some(&menu::placeholder(added)))
             .context("Failed to select menu item")?;
 
-        if selection == complete_option {
+        let action = menu::parse(&selection)?;
+        if action == MenuAction::Complete {
             break;
-        } else if selection == add_field_option {
-            // Add new field
-            let key = fuzzel::select_or_input(&existing_keys, Some("Field name"))
-                .context("Failed to get field name")?;
+        }
 
-            let request_input_fn = if Field::is_key_sensitive(&key) {
-                fuzzel::request_password
-            } else {
-                fuzzel::request_input
+        if let Err(err) = apply_action(config, &mut data, existing_keys, action) {
+            let cancelled = err.chain().any(|cause| cause.is::<fuzzel::Cancelled>());
+            if !(cancelled && config.on_cancel == OnCancel::Back) {
+                return Err(err);
+            }
+        }
+    }
+
+    Ok(data)
+}
+
+/// Ask for the key of a new field, normalized if `normalize_keys` is enabled
+///
+/// The key as typed is also returned if normalizing changed it, so prompts can
+/// mention it.
+fn request_key(config: &Config, existing_keys: &[String]) -> Result<(String, Option<String>)> {
+    let typed = fuzzel::select_or_input(existing_keys, Some("Field name"))
+        .context("Failed to get field name")?;
+    if !config.normalize_keys {
+        return Ok((typed, None));
+    }
+
+    let key = field::normalize_key(&typed, &config.key_aliases);
+    let typed = (key != typed).then_some(typed);
+    Ok((key, typed))
+}
+
+/// Carry out an action chosen in the store menu
+fn apply_action(
+    config: &Config,
+    data: &mut Secret,
+    existing_keys: &[String],
+    action: MenuAction,
+) -> Result<()> {
+    match action {
+        // Handled by edit_fields, which stops showing the menu
+        MenuAction::Complete => {}
+        MenuAction::AddField => {
+            let (key, typed) = request_key(config, existing_keys)?;
+
+            let sensitive = data.is_sensitive(&key);
+            let prompt = match typed {
+                Some(typed) => format!("Value for '{}' (from '{}')", key, typed),
+                None => format!("Value for '{}'", key),
+            };
+            let Some(value) = request_value(config, &key, sensitive, None, &prompt)
+                .with_context(|| format!("Failed to get value for '{}'", key))?
+            else {
+                return Ok(());
             };
-            let value = request_input_fn(Some(&format!("Value for '{}'", key)))
-                .context("Failed to get field value")?;
 
             data.insert(key, value);
-        } else {
-            // Edit existing field
-            let field = Field::parse_from_display(&selection)?;
-            let request_input_fn = if field.is_sensitive() {
-                fuzzel::request_password
-            } else {
-                fuzzel::request_input
+        }
+        MenuAction::ScanQr => {
+            // Read a TOTP seed from an otpauth:// QR code on screen
+            let otp = otpauth::scan(&config.qr_scanner_command)
+                .context("Failed to scan otpauth QR code")?;
+
+            data.insert(otpauth::TOTP_FIELD.to_string(), otp.secret);
+        }
+        MenuAction::FromCommand => {
+            // Store the output of a command without it appearing on screen
+            let (key, _) = request_key(config, existing_keys)?;
+            let command = fuzzel::request_input(Some(&format!("Command for '{}'", key)))
+                .with_context(|| format!("Failed to get command for '{}'", key))?;
+            let value = shell::output(&command)
+                .with_context(|| format!("Failed to get value for '{}' from command", key))?;
+
+            data.insert(key, value);
+        }
+        MenuAction::FromClipboard => {
+            // Take a value copied elsewhere, such as a long token, without showing it
+            let (key, _) = request_key(config, existing_keys)?;
+            let value = clipboard::paste(config.clip_tool, config.paste_trim_newline)
+                .with_context(|| format!("Failed to get value for '{}' from clipboard", key))?;
+
+            data.insert(key, value);
+        }
+        MenuAction::ToggleSubmit => {
+            let mut keys: Vec<String> = data.keys().cloned().collect();
+            keys.sort_by(|a, b| field::compare_keys(&config.field_order, a, b));
+            let key = fuzzel::select(&keys, Some("Submit with Enter after typing"))
+                .context("Failed to select field")?;
+
+            let submits = data.submits(&key);
+            data.set_submits(&key, !submits);
+        }
+        MenuAction::ToggleSensitive => {
+            let mut keys: Vec<String> = data.keys().cloned().collect();
+            keys.sort_by(|a, b| field::compare_keys(&config.field_order, a, b));
+            let key = fuzzel::select(&keys, Some("Toggle sensitivity"))
+                .context("Failed to select field")?;
+
+            // Only keep an override while it differs from what the key implies
+            let sensitive = !data.is_sensitive(&key);
+            let overridden = sensitive != Field::is_key_sensitive(&key);
+            data.set_sensitivity_override(&key, overridden.then_some(sensitive));
+        }
+        MenuAction::EditField(field) => {
+            // The selected menu item only holds the displayed, possibly masked value
+            let current = data.get_value(&field.key).map(str::to_string);
+            let Some(value) = request_value(
+                config,
+                &field.key,
+                data.is_sensitive(&field.key),
+                current.as_deref(),
+                &format!("New value for '{}'", field.key),
+            )
+            .with_context(|| format!("Failed to get value for '{}'", field.key))?
+            else {
+                return Ok(());
             };
-            let value = request_input_fn(Some(&format!("New value for '{}'", field.key)))
-                .context("Failed to get field value")?;
 
             data.insert(field.key, value);
         }
     }
 
+    Ok(())
+}
+
+/// Check if field names of other secrets should be suggested while storing
+///
+/// Gathering them decrypts every secret, which is slow for large collections.
+fn suggest_keys(config: &Config, secret_count: usize) -> bool {
+    config.key_suggestions
+        && (config.key_suggestions_max_secrets == 0
+            || secret_count <= config.key_suggestions_max_secrets)
+}
+
+async fn store(
+    config: &Config,
+    url: Option<String>,
+    expires_at: Option<String>,
+    prefix: Option<String>,
+    dry_run: bool,
+) -> Result<()> {
+    let expires_at = expires_at
+        .map(|value| {
+            timestamp::parse_rfc3339(&value).ok_or_else(|| {
+                anyhow::anyhow!(
+                    "Invalid expiry '{}', expected an RFC 3339 time such as 2025-01-31T00:00:00Z",
+                    value
+                )
+            })
+        })
+        .transpose()?;
+
+    // A dry run starts from an empty secret, never connecting to the Secret Service
+    let (_lock, all_secrets) = if dry_run {
+        (None, Vec::new())
+    } else {
+        // Concurrent stores could both create an item for the same new label
+        let lock = Lock::acquire(&secrets::collection_label())?;
+        let mut all_secrets = secrets::secrets()
+            .await
+            .context("Failed to retrieve secrets")?;
+        all_secrets.sort();
+        (Some(lock), all_secrets)
+    };
+
+    // Start fetching existing keys concurrently, it decrypts every secret
+    let existing_keys_task = (!dry_run && suggest_keys(config, all_secrets.len()))
+        .then(|| tokio::spawn(async move { secrets::all_field_keys().await }));
+
+    // Offer the domain of the URL as the first label, the user may still type another
+    let mut label_items = all_secrets.clone();
+    if let Some(suggestion) = url.as_deref().and_then(label::suggest_from_url) {
+        label_items.retain(|label| *label != suggestion);
+        label_items.insert(0, suggestion);
+    }
+
+    let requested_secret = fuzzel::select_or_input(&label_items, Some("Label"))
+        .context("Failed to get service name")?;
+    let requested_secret = match prefix {
+        Some(prefix) => label::with_prefix(&prefix, &requested_secret),
+        None => requested_secret,
+    };
+
+    // Check if service already exists and load its data
+    let existing_secret = all_secrets
+        .iter()
+        .find(|secret| secret.to_string() == requested_secret);
+
+    let (mut data, replaces) = match existing_secret {
+        Some(secret) => {
+            let (data, path) = secrets::select_item(secret)
+                .await
+                .with_context(|| format!("Failed to get existing secret data for '{}'", secret))?;
+            (data, Some(path))
+        }
+        None => (Secret::new(), None),
+    };
+    let original = existing_secret.map(|_| data.clone());
+    data.prune_previous(timestamp::now(), retention_seconds(config));
+    if let Some(url) = url {
+        if !data.contains_key("url") {
+            data.insert("url".to_string(), url);
+        }
+    }
+    if expires_at.is_some() {
+        data.set_expires_at(expires_at);
+    }
+
+    let mut existing_keys = match existing_keys_task {
+        Some(task) => task
+            .await
+            .context("Failed to join existing keys task")?
+            .context("Failed to retrieve existing field keys")?,
+        None => Vec::new(),
+    };
+    existing_keys.sort();
+    let existing_keys = existing_keys;
+
+    let data = edit_fields(config, data, &existing_keys).await?;
+
     if data.is_empty() {
-        return Err(anyhow::anyhow!("No fields provided"));
+        return Err(SecretError::Empty(requested_secret).into());
+    }
+
+    if dry_run {
+        let payload = secrets::encode_payload(&data)?;
+        eprintln!(
+            "Dry run, '{}' was not stored. The payload below is plain text, sensitive values included:",
+            requested_secret
+        );
+        eprintln!("{}", payload);
+        return Ok(());
     }
 
-    secrets::store(&requested_secret, data)
+    // Avoid rewriting an unchanged secret
+    if original.as_ref() == Some(&data) {
+        println!("No changes");
+        return Ok(());
+    }
+    let diff = data.diff(original.as_ref().unwrap_or(&Secret::new()));
+
+    if let Some(original) = original.filter(|_| config.backup) {
+        let path = backup::write(&requested_secret, &original, config.max_backups)
+            .with_context(|| format!("Failed to back up '{}'", requested_secret))?;
+        println!("Backed up the previous contents to {}", path.display());
+    }
+
+    secrets::store(&requested_secret, data, replaces.as_ref())
         .await
-        .context("Failed to store secret")?;
+        .with_context(|| format!("Failed to store secret '{}'", requested_secret))?;
 
     println!("Secret stored successfully");
+    // Only field keys are printed, never values
+    if !diff.is_empty() {
+        println!("{}", diff);
+    }
+    if config.notify {
+        notify::send(&format!("Stored {}", requested_secret));
+    }
 
     Ok(())
 }
 
+async fn duplicate(config: &Config, edit: bool) -> Result<()> {
+    // The new label is checked to be free before storing, like in `store`
+    let _lock = Lock::acquire(&secrets::collection_label())?;
+
+    let mut all_secrets = secrets::secrets()
+        .await
+        .context("Failed to retrieve secrets")?;
+    all_secrets.sort();
+    let all_secrets = all_secrets;
+
+    let source = fuzzel::select(&all_secrets, Some("Secret to duplicate"))
+        .context("Failed to select secret")?;
+
+    let new_label = fuzzel::request_input(Some(&format!("New label for copy of '{}'", source)))
+        .context("Failed to get new label")?;
+
+    if new_label.is_empty() {
+        return Err(anyhow::anyhow!("No label provided"));
+    }
+    if all_secrets.contains(&new_label) {
+        return Err(SecretError::Duplicate(new_label).into());
+    }
+
+    let mut data = secrets::get_data(&source)
+        .await
+        .with_context(|| format!("Failed to get secret data for '{}'", source))?;
+
+    if edit {
+        let mut existing_keys = if suggest_keys(config, all_secrets.len()) {
+            secrets::all_field_keys()
+                .await
+                .context("Failed to retrieve existing field keys")?
+        } else {
+            Vec::new()
+        };
+        existing_keys.sort();
+        data = edit_fields(config, data, &existing_keys).await?;
+    }
+
+    if data.is_empty() {
+        return Err(SecretError::Empty(new_label).into());
+    }
+
+    secrets::store(&new_label, data, None)
+        .await
+        .with_context(|| format!("Failed to store secret '{}'", new_label))?;
+
+    println!("Secret duplicated successfully");
+    if config.notify {
+        notify::send(&format!("Stored {}", new_label));
+    }
+
+    Ok(())
+}
+
+async fn exec(label: Option<String>, command: Vec<String>) -> Result<()> {
+    let label = match label {
+        Some(label) => label,
+        None => {
+            let mut all_secrets = secrets::secrets()
+                .await
+                .context("Failed to retrieve secrets")?;
+            all_secrets.sort();
+            fuzzel::select(&all_secrets, Some("Select secret"))
+                .context("Failed to select secret")?
+        }
+    };
+
+    let data = secrets::get_data(&label)
+        .await
+        .with_context(|| format!("Failed to get secret data for '{}'", label))?;
+    let vars = exec::env_vars(&data)?;
+
+    // Replace this process, so the values only live in the command's environment
+    let err = Command::new(&command[0])
+        .args(&command[1..])
+        .envs(vars)
+        .exec();
+
+    Err(err).with_context(|| format!("Failed to execute {}", command[0]))
+}
+
+async fn unlock() -> Result<()> {
+    match secrets::unlock()
+        .await
+        .context("Failed to unlock collection")?
+    {
+        UnlockStatus::Unlocked => println!("Collection unlocked"),
+        UnlockStatus::AlreadyUnlocked => println!("Collection was already unlocked"),
+        UnlockStatus::NotFound => return Err(NotFound::Collection.into()),
+    }
+
+    Ok(())
+}
+
+async fn collections() -> Result<()> {
+    let mut collections = secrets::collections()
+        .await
+        .context("Failed to retrieve collections")?;
+    collections.sort_by(|a, b| a.label.cmp(&b.label));
+
+    for collection in collections {
+        let indicator = if collection.locked { "[locked]" } else { "" };
+        println!("{:<8} {}", indicator, collection.label);
+    }
+
+    Ok(())
+}
+
+async fn list(suggest_dedupe: bool, expired: bool, tree: bool, sort: ListSort) -> Result<()> {
+    let labels = secrets::read_labels()
+        .await
+        .context("Failed to retrieve secrets")?;
+    // List what can be read, a locked or broken item shouldn't hide the others
+    if !labels.failures.is_empty() {
+        eprintln!("{} secrets couldn't be read:", labels.failures.len());
+        for err in &labels.failures {
+            eprintln!("  {:#}", err);
+        }
+    }
+    let mut all_secrets = labels.read;
+    all_secrets.sort();
+
+    match sort {
+        ListSort::Alpha => {}
+        ListSort::Recent => {
+            let history = History::load().context("Failed to load history")?;
+            all_secrets.sort_by(|a, b| history.compare_recent(a, b));
+        }
+        ListSort::Fields => {
+            eprintln!("Sorting by field count decrypts every secret, this may take a while");
+            let counts =
+                try_join_all(all_secrets.iter().map(|label| async move {
+                    secrets::get_data(label).await.map(|data| data.len())
+                }))
+                .await
+                .context("Failed to count fields")?;
+
+            let mut counted: Vec<(String, usize)> = all_secrets.into_iter().zip(counts).collect();
+            counted.sort_by(|a, b| label::compare_field_counts((&a.0, a.1), (&b.0, b.1)));
+            all_secrets = counted.into_iter().map(|(label, _)| label).collect();
+        }
+    }
+
+    if suggest_dedupe {
+        for group in label::duplicate_groups(&all_secrets) {
+            let quoted: Vec<String> = group.iter().map(|l| format!("'{}'", l)).collect();
+            println!("{}", quoted.join(", "));
+        }
+    } else if tree {
+        for line in label::tree(&all_secrets) {
+            println!("{}", line.text);
+        }
+    } else if expired {
+        let now = timestamp::now();
+        for label in all_secrets {
+            let data = secrets::get_data(&label)
+                .await
+                .with_context(|| format!("Failed to get secret data for '{}'", label))?;
+            if let Some(expires_at) = data.expires_at().filter(|t| *t <= now) {
+                println!("{}  {}", timestamp::format_rfc3339(expires_at), label);
+            }
+        }
+    } else {
+        for label in all_secrets {
+            println!("{}", label);
+        }
+    }
+
+    Ok(())
+}
+
+async fn delete(label: Option<String>, multi: bool) -> Result<()> {
+    let labels = match label {
+        Some(label) => vec![label],
+        None => {
+            let mut all_secrets = secrets::secrets()
+                .await
+                .context("Failed to retrieve secrets")?;
+            all_secrets.sort();
+            if multi {
+                let selected = fuzzel::select_multi(&all_secrets, Some("Secrets to delete"))
+                    .context("Failed to select secrets")?;
+                // Only delete what was offered, not whatever was typed
+                selected
+                    .into_iter()
+                    .filter(|label| all_secrets.contains(label))
+                    .collect()
+            } else {
+                vec![fuzzel::select(&all_secrets, Some("Secret to delete"))
+                    .context("Failed to select secret")?]
+            }
+        }
+    };
+
+    if labels.is_empty() {
+        println!("No secrets selected");
+        return Ok(());
+    }
+
+    let question = match labels.as_slice() {
+        [label] => format!("Delete '{}'?", label),
+        _ => format!("Delete {} secrets?", labels.len()),
+    };
+    if !fuzzel::confirm(&question).context("Failed to confirm deletion")? {
+        println!("Nothing deleted");
+        return Ok(());
+    }
+
+    for label in &labels {
+        secrets::delete(label)
+            .await
+            .with_context(|| format!("Failed to delete '{}'", label))?;
+    }
+
+    println!("Deleted {} secrets", labels.len());
+
+    Ok(())
+}
+
+async fn rotate(
+    config: &Config,
+    label: Option<String>,
+    field: Option<String>,
+    undo: bool,
+    clipboard: bool,
+) -> Result<()> {
+    let _lock = Lock::acquire(&secrets::collection_label())?;
+
+    let label = match label {
+        Some(label) => label,
+        None => {
+            let mut all_secrets = secrets::secrets()
+                .await
+                .context("Failed to retrieve secrets")?;
+            all_secrets.sort();
+            fuzzel::select(&all_secrets, Some("Secret to rotate"))
+                .context("Failed to select secret")?
+        }
+    };
+
+    let (mut data, replaces) = secrets::select_item(&label)
+        .await
+        .with_context(|| format!("Failed to get secret data for '{}'", label))?;
+    let now = timestamp::now();
+    data.prune_previous(now, retention_seconds(config));
+
+    if undo {
+        let key = match field {
+            Some(field) => field,
+            None => match data.previous_keys().as_slice() {
+                [] => {
+                    return Err(anyhow::anyhow!(
+                        "No rotated fields to restore in '{}'",
+                        label
+                    ))
+                }
+                [key] => key.clone(),
+                keys => fuzzel::select(keys, Some("Field to restore"))
+                    .context("Failed to select field")?,
+            },
+        };
+        let (previous, _) = data.previous(&key).ok_or_else(|| {
+            anyhow::anyhow!("No previous value of '{}' within the retention period", key)
+        })?;
+        let question = format!("Restore the previous value of '{}'?", key);
+        if !fuzzel::confirm(&question).context("Failed to confirm restore")? {
+            println!("Nothing restored");
+            return Ok(());
+        }
+
+        data.insert(key.clone(), previous.to_string());
+        data.remove_previous(&key);
+        secrets::store(&label, data, Some(&replaces))
+            .await
+            .with_context(|| format!("Failed to store secret '{}'", label))?;
+
+        println!("Restored the previous value of '{}'", key);
+        return Ok(());
+    }
+
+    let key = match field {
+        Some(field) => field,
+        None => {
+            let mut keys: Vec<String> = data
+                .keys()
+                .filter(|key| data.is_sensitive(key))
+                .cloned()
+                .collect();
+            keys.sort_by(|a, b| field::compare_keys(&config.field_order, a, b));
+            match keys.as_slice() {
+                [] => return Err(anyhow::anyhow!("No sensitive fields in '{}'", label)),
+                [key] => key.clone(),
+                _ => fuzzel::select(&keys, Some("Field to rotate"))
+                    .context("Failed to select field")?,
+            }
+        }
+    };
+    if !data.contains_key(&key) {
+        return Err(NotFound::Field(key).into());
+    }
+
+    let value = generator::generate(
+        config
+            .generated_length
+            .max(config.password_policy.min_length),
+    );
+    data.set_previous(&key, now);
+    data.insert(key.clone(), value.clone());
+    let sensitive = data.is_sensitive(&key);
+    secrets::store(&label, data, Some(&replaces))
+        .await
+        .with_context(|| format!("Failed to store secret '{}'", label))?;
+
+    println!("Rotated '{}'; undo with `rotate --undo`", key);
+    if clipboard {
+        copy_value(config, &label, &key, sensitive, &value).await?;
+    } else {
+        type_value(config, &value)?;
+        if config.notify {
+            notify::send(&format!("Typed new {} for {}", key, label));
+        }
+    }
+
+    Ok(())
+}
+
+/// How long previous values of rotated fields are kept, in seconds
+fn retention_seconds(config: &Config) -> i64 {
+    config.rotation_retention_hours.saturating_mul(3600) as i64
+}
+
+async fn dedupe() -> Result<()> {
+    let _lock = Lock::acquire(&secrets::collection_label())?;
+
+    let all_secrets = secrets::secrets()
+        .await
+        .context("Failed to retrieve secrets")?;
+    let groups = label::duplicate_groups(&all_secrets);
+
+    if groups.is_empty() {
+        println!("No duplicate labels found");
+        return Ok(());
+    }
+
+    for group in groups {
+        // Pick one of the variants, or type a new canonical label
+        let canonical = fuzzel::select_or_input(&group, Some("Canonical label"))
+            .context("Failed to get canonical label")?;
+        // Storing the merged secret would replace an unrelated one
+        if !group.contains(&canonical) && all_secrets.contains(&canonical) {
+            return Err(SecretError::Duplicate(canonical).into());
+        }
+
+        let (mut merged, replaces) = match group.iter().find(|l| **l == canonical) {
+            Some(label) => {
+                let (data, path) = secrets::get_item(label)
+                    .await
+                    .with_context(|| format!("Failed to get secret data for '{}'", label))?;
+                (data, Some(path))
+            }
+            None => (Secret::new(), None),
+        };
+        let mut conflicts = Vec::new();
+        for label in group.iter().filter(|l| **l != canonical) {
+            let data = secrets::get_data(label)
+                .await
+                .with_context(|| format!("Failed to get secret data for '{}'", label))?;
+            conflicts.extend(merged.merge(data));
+        }
+
+        let others: Vec<&String> = group.iter().filter(|l| **l != canonical).collect();
+        let question = format!("Merge {} secrets into '{}'?", others.len(), canonical);
+        if !fuzzel::confirm(&question).context("Failed to confirm merge")? {
+            println!("Skipped '{}'", canonical);
+            continue;
+        }
+
+        secrets::store(&canonical, merged, replaces.as_ref())
+            .await
+            .with_context(|| format!("Failed to store merged secret '{}'", canonical))?;
+        for label in others {
+            secrets::delete(label)
+                .await
+                .with_context(|| format!("Failed to delete merged secret '{}'", label))?;
+        }
+
+        println!("Merged into '{}'", canonical);
+        if !conflicts.is_empty() {
+            println!(
+                "Kept the values of '{}' for: {}",
+                canonical,
+                conflicts.join(", ")
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Store secrets, asking `overwrite` whether to replace each one whose label
+/// already exists. Returns the number of secrets stored.
+async fn store_all<F>(
+    items: impl IntoIterator<Item = (String, Secret)>,
+    mut overwrite: F,
+) -> Result<usize>
+where
+    F: FnMut(&str) -> Result<bool>,
+{
+    let mut existing = secrets::secrets()
+        .await
+        .context("Failed to retrieve secrets")?;
+
+    let mut stored = 0;
+    for (label, data) in items {
+        let replaces = if existing.contains(&label) {
+            if !overwrite(&label)? {
+                continue;
+            }
+            Some(secrets::item_path(&label).await?)
+        } else {
+            None
+        };
+        secrets::store(&label, data, replaces.as_ref())
+            .await
+            .with_context(|| format!("Failed to store '{}'", label))?;
+        existing.push(label);
+        stored += 1;
+    }
+
+    Ok(stored)
+}
+
+async fn import(from_schema: &str) -> Result<()> {
+    let _lock = Lock::acquire(&secrets::collection_label())?;
+
+    let items = secrets::items_from_schema(from_schema)
+        .await
+        .with_context(|| format!("Failed to read items of schema {}", from_schema))?;
+
+    let imported = store_all(items, |label| {
+        eprintln!(
+            "Skipping '{}': a secret with this label already exists",
+            label
+        );
+        Ok(false)
+    })
+    .await?;
+
+    println!("Imported {} secrets", imported);
+
+    Ok(())
+}
+
+async fn tags(key: Option<&str>) -> Result<()> {
+    let summaries = secrets::read_summaries()
+        .await
+        .and_then(secrets::Partial::readable)
+        .context("Failed to retrieve secrets")?;
+    for tag in secrets::tags(&summaries, key) {
+        println!("{}", tag);
+    }
+    Ok(())
+}
+
+/// Report secrets that fail to decode, failing if there are any
+async fn check() -> Result<()> {
+    let mut results = secrets::check_all()
+        .await
+        .context("Failed to retrieve secrets")?;
+    results.sort_by(|a, b| a.label.cmp(&b.label));
+
+    let mut failed = 0;
+    for result in &results {
+        if let Err(err) = &result.result {
+            println!("{}: {:#}", result.label, err);
+            failed += 1;
+        }
+    }
+
+    if failed > 0 {
+        return Err(anyhow::anyhow!(
+            "{} of {} secrets failed to decode",
+            failed,
+            results.len()
+        ));
+    }
+
+    println!("All {} secrets decode correctly", results.len());
+    Ok(())
+}
+
+async fn import_pass(input: &Path) -> Result<()> {
+    let entries = export::read_pass(input)
+        .with_context(|| format!("Failed to read pass entries in {}", input.display()))?;
+
+    let _lock = Lock::acquire(&secrets::collection_label())?;
+    let imported = store_all(entries, |label| {
+        eprintln!(
+            "Skipping '{}': a secret with this label already exists",
+            label
+        );
+        Ok(false)
+    })
+    .await?;
+
+    println!("Imported {} secrets", imported);
+
+    Ok(())
+}
+
+async fn import_keepass(input: &Path) -> Result<()> {
+    let text = std::fs::read_to_string(input)
+        .with_context(|| format!("Failed to read {}", input.display()))?;
+    let import = keepass::parse(&text)
+        .with_context(|| format!("Failed to parse KeePass export {}", input.display()))?;
+    for failure in &import.failures {
+        eprintln!("Skipping {}", failure);
+    }
+
+    let _lock = Lock::acquire(&secrets::collection_label())?;
+    let imported = store_all(import.secrets, |label| {
+        eprintln!(
+            "Skipping '{}': a secret with this label already exists",
+            label
+        );
+        Ok(false)
+    })
+    .await?;
+
+    println!("Imported {} secrets", imported);
+    if !import.failures.is_empty() {
+        println!("{} rows could not be imported", import.failures.len());
+    }
+
+    Ok(())
+}
+
+async fn restore(input: Option<PathBuf>, list: bool) -> Result<()> {
+    if list {
+        let dir = backup::dir().context("Failed to determine backup location")?;
+        for path in backup::list(&dir)? {
+            println!("{}", path.display());
+        }
+        return Ok(());
+    }
+
+    let Some(input) = input else {
+        return Err(anyhow::anyhow!("No backup given, pass --input or --list"));
+    };
+    let json = std::fs::read_to_string(&input)
+        .with_context(|| format!("Failed to read {}", input.display()))?;
+    let backup =
+        export::from_json(&json).with_context(|| format!("Invalid backup {}", input.display()))?;
+    export::validate(&backup).with_context(|| format!("Invalid backup {}", input.display()))?;
+
+    let _lock = Lock::acquire(&secrets::collection_label())?;
+    let restored = store_all(backup, |label| {
+        fuzzel::confirm(&format!("Overwrite existing '{}'?", label))
+            .context("Failed to confirm overwrite")
+    })
+    .await?;
+
+    println!("Restored {} secrets", restored);
+
+    Ok(())
+}
+
+/// Print the selected item, never the value of a secret
+async fn select(items: Vec<String>, stdin: bool, placeholder: Option<String>) -> Result<()> {
+    let items = if stdin {
+        std::io::stdin()
+            .lines()
+            .collect::<std::io::Result<Vec<String>>>()
+            .context("Failed to read items from stdin")?
+    } else if items.is_empty() {
+        let mut all_secrets = secrets::secrets()
+            .await
+            .context("Failed to retrieve secrets")?;
+        all_secrets.sort();
+        all_secrets
+    } else {
+        items
+    };
+
+    let selection = fuzzel::select(&items, placeholder.as_deref().or(Some("Select")))
+        .context("Failed to select item")?;
+    println!("{}", selection);
+
+    Ok(())
+}
+
+async fn serve(config: &Config) -> Result<()> {
+    let path = serve::socket_path();
+    let listener = serve::bind(&path).await?;
+    println!("Listening on {}", path.display());
+
+    loop {
+        let (stream, _) = listener
+            .accept()
+            .await
+            .context("Failed to accept connection")?;
+        // Requests are handled one at a time, as each one drives fuzzel
+        if let Err(err) = handle_request(config, stream).await {
+            eprintln!("Failed to handle request: {:#}", err);
+        }
+    }
+}
+
+/// Apply the options of a retrieve request that override the configuration
+fn apply_retrieve_args(config: &mut Config, args: &RetrieveArgs) {
+    if let Some(clip_tool) = args.clip_tool {
+        config.clip_tool = clip_tool;
+    }
+    if let Some(type_mode) = args.type_mode {
+        config.type_mode = type_mode;
+    }
+    config.clipboard_trim &= !args.no_trim;
+}
+
+/// Run one request and answer with its exit code and, on failure, the error
+async fn handle_request(config: &Config, stream: UnixStream) -> Result<()> {
+    let (reader, mut writer) = stream.into_split();
+    let mut line = String::new();
+    // A client that never sends its request mustn't block the ones after it
+    tokio::time::timeout(
+        serve::REQUEST_TIMEOUT,
+        BufReader::new(reader).read_line(&mut line),
+    )
+    .await
+    .context("Timed out reading request")?
+    .context("Failed to read request")?;
+
+    let mut config = config.clone();
+    let result = match ServeRequest::try_parse_from(line.split_whitespace()) {
+        Ok(ServeRequest::Retrieve(args)) => {
+            apply_retrieve_args(&mut config, &args);
+            retrieve(&config, args).await
+        }
+        Ok(ServeRequest::Store { url }) => store(&config, url, None, None, false).await,
+        Err(err) => Err(err.into()),
+    };
+
+    let response = match result {
+        Ok(()) => "0 ok\n".to_string(),
+        Err(err) => {
+            // Keep the response on one line, clap errors span several
+            let message = format!("{:#}", err).replace('\n', " ");
+            format!("{} {}\n", exit_code(&err), message)
+        }
+    };
+    writer
+        .write_all(response.as_bytes())
+        .await
+        .context("Failed to send response")
+}
+
+async fn purge() -> Result<()> {
+    let items = secrets::collection_items()
+        .await
+        .context("Failed to count the items of the collection")?;
+    let collection = secrets::collection_label();
+    // Deleting the collection would take the items of other applications along
+    if items.foreign > 0 {
+        return Err(anyhow::anyhow!(
+            "Collection '{}' holds {} items not stored by fuzzel-secrets, nothing deleted",
+            collection,
+            items.foreign
+        ));
+    }
+
+    // Typing the label rather than picking "Yes" makes accidents unlikely
+    let prompt = format!(
+        "Type '{}' to delete all {} secrets",
+        collection, items.secrets
+    );
+    let answer = fuzzel::request_input(Some(&prompt)).context("Failed to confirm purge")?;
+    if answer != collection {
+        return Err(anyhow::anyhow!(
+            "Confirmation did not match, nothing deleted"
+        ));
+    }
+
+    let count = secrets::delete_collection()
+        .await
+        .context("Failed to purge secrets")?;
+
+    println!("Deleted {} secrets", count);
+
+    Ok(())
+}
+
+/// Exit code for errors without a more specific code
+const EXIT_ERROR: u8 = 1;
+/// Exit code when the user dismissed a prompt
+const EXIT_CANCELLED: u8 = 2;
+/// Exit code when the requested secret, field or collection doesn't exist
+const EXIT_NOT_FOUND: u8 = 3;
+/// Exit code when a secret has no fields to store or retrieve
+const EXIT_EMPTY: u8 = 4;
+/// Exit code when a secret with the label already exists
+const EXIT_DUPLICATE: u8 = 5;
+
+/// Map an error to the documented exit code of its cause
+fn exit_code(err: &anyhow::Error) -> u8 {
+    match SecretError::of(err) {
+        Some(SecretError::Cancelled) => EXIT_CANCELLED,
+        Some(SecretError::NotFound(_)) => EXIT_NOT_FOUND,
+        Some(SecretError::Empty(_)) => EXIT_EMPTY,
+        Some(SecretError::Duplicate(_)) => EXIT_DUPLICATE,
+        None => EXIT_ERROR,
+    }
+}
+
 #[tokio::main]
-async fn main() -> Result<()> {
+async fn main() -> ExitCode {
+    match run().await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("Error: {:?}", err);
+            ExitCode::from(exit_code(&err))
+        }
+    }
+}
+
+async fn run() -> Result<()> {
     let cli = Cli::parse();
+    let mut config = Config::load().context("Failed to load configuration")?;
+    config.auto_unlock &= !cli.no_auto_unlock && !cli.non_interactive;
+    config.notify |= cli.notify;
+    if let Some(path) = cli.fuzzel_config {
+        config.fuzzel_config = Some(path);
+    }
+    if let Some(path) = &config.fuzzel_config {
+        if !path.is_file() {
+            return Err(anyhow::anyhow!(
+                "fuzzel config file not found: {}",
+                path.display()
+            ));
+        }
+    }
+    settings::set(Settings {
+        auto_unlock: config.auto_unlock,
+        lenient: cli.lenient,
+        searchable_fields: config.searchable_fields.clone(),
+        partial_mask_fields: config.partial_mask_fields.clone(),
+        schema: std::env::var("FUZZEL_SECRETS_SCHEMA")
+            .ok()
+            .filter(|schema| !schema.is_empty())
+            .or_else(|| config.schema.clone()),
+        collection: cli
+            .collection
+            .clone()
+            .or_else(|| std::env::var("FUZZEL_SECRETS_COLLECTION").ok())
+            .filter(|label| !label.is_empty())
+            .map(|name| settings::resolve_collection(name, &config.collection_aliases)),
+        fuzzel_bin: std::env::var_os(fuzzel::FUZZEL_BIN_VAR)
+            .filter(|bin| !bin.is_empty())
+            .map(PathBuf::from),
+        fuzzel_config: config.fuzzel_config.clone(),
+        fuzzel_cache: config.fuzzel_cache,
+        fuzzel_width: cli.width,
+        interactive: !cli.non_interactive,
+    });
 
     match cli.command {
-        Commands::Store => store().await?,
-        Commands::Retrieve => retrieve().await?,
+        Commands::Store {
+            mask_all_inputs,
+            url,
+            expires_at,
+            prefix,
+            backup,
+            on_cancel,
+            no_key_suggestions,
+            sort_fields,
+            dry_run,
+        } => {
+            config.mask_all_inputs |= mask_all_inputs;
+            config.backup |= backup;
+            config.key_suggestions &= !no_key_suggestions;
+            if let Some(sort_fields) = sort_fields {
+                config.sort_fields = sort_fields;
+            }
+            if let Some(on_cancel) = on_cancel {
+                config.on_cancel = on_cancel;
+            }
+            store(&config, url, expires_at, prefix, dry_run).await?
+        }
+        Commands::Retrieve(args) => {
+            apply_retrieve_args(&mut config, &args);
+            retrieve(&config, args).await?
+        }
+        Commands::Print { label, field, raw } => print(&config, &label, &field, raw).await?,
+        Commands::Copy {
+            label,
+            field,
+            clip_tool,
+            no_trim,
+        } => {
+            config.clipboard_trim &= !no_trim;
+            if let Some(clip_tool) = clip_tool {
+                config.clip_tool = clip_tool;
+            }
+            copy(&config, &label, &field).await?
+        }
+        Commands::Show {
+            label,
+            reveal,
+            reveal_timeout,
+            output,
+            attributes,
+        } => show(&config, label, reveal, reveal_timeout, output, attributes).await?,
+        Commands::Export {
+            output,
+            format,
+            no_sensitive,
+            only,
+        } => export(output, format, no_sensitive, only).await?,
+        Commands::Duplicate { edit } => duplicate(&config, edit).await?,
+        Commands::Exec { label, command } => exec(label, command).await?,
+        Commands::Unlock => unlock().await?,
+        Commands::Collections => collections().await?,
+        Commands::List {
+            suggest_dedupe,
+            expired,
+            tree,
+            sort,
+        } => list(suggest_dedupe, expired, tree, sort).await?,
+        Commands::Delete { label, multi } => delete(label, multi).await?,
+        Commands::Rotate {
+            label,
+            field,
+            undo,
+            clipboard,
+        } => rotate(&config, label, field, undo, clipboard).await?,
+        Commands::Dedupe => dedupe().await?,
+        Commands::Check => check().await?,
+        Commands::Tags { key } => tags(key.as_deref()).await?,
+        Commands::Select {
+            items,
+            stdin,
+            placeholder,
+        } => select(items, stdin, placeholder).await?,
+        Commands::Purge => purge().await?,
+        Commands::Serve => serve(&config).await?,
+        Commands::Import {
+            from_schema,
+            format,
+            input,
+        } => match (from_schema, format, input) {
+            (Some(schema), _, _) => import(&schema).await?,
+            (None, Some(ImportFormat::KeepassCsv), Some(input)) => import_keepass(&input).await?,
+            (None, Some(ImportFormat::Pass), Some(input)) => import_pass(&input).await?,
+            _ => unreachable!("clap requires --from-schema or --format with --input"),
+        },
+        Commands::Restore { input, list } => restore(input, list).await?,
     }
 
-    Ok(())
+    finish_clipboard_clears().await
 }
@@ -0,0 +1,134 @@
+use crate::field::{self, Field};
+use crate::secret::Secret;
+use anyhow::Result;
+
+pub const ADD_FIELD_OPTION: &str = "+   Add field";
+pub const COMPLETE_OPTION: &str = "✓   Complete";
+pub const SCAN_QR_OPTION: &str = "📷   Scan QR";
+pub const FROM_COMMAND_OPTION: &str = "⚙   From command";
+pub const FROM_CLIPBOARD_OPTION: &str = "📋   From clipboard";
+pub const TOGGLE_SUBMIT_OPTION: &str = "⏎   Toggle auto-submit";
+pub const TOGGLE_SENSITIVE_OPTION: &str = "🔒   Toggle sensitivity";
+
+/// An action chosen in the store menu
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MenuAction {
+    AddField,
+    Complete,
+    ScanQr,
+    FromCommand,
+    FromClipboard,
+    ToggleSubmit,
+    ToggleSensitive,
+    EditField(Field),
+}
+
+/// Build the store menu: the fixed options first, then the secret's fields
+/// sorted by [`field::compare_keys`], or in the order they were added unless
+/// `sort` is set
+pub fn items(data: &Secret, field_order: &[String], sort: bool) -> Vec<String> {
+    let mut menu_items: Vec<String> = vec![
+        ADD_FIELD_OPTION.to_string(),
+        COMPLETE_OPTION.to_string(),
+        SCAN_QR_OPTION.to_string(),
+        FROM_COMMAND_OPTION.to_string(),
+        FROM_CLIPBOARD_OPTION.to_string(),
+        TOGGLE_SUBMIT_OPTION.to_string(),
+        TOGGLE_SENSITIVE_OPTION.to_string(),
+    ];
+
+    let mut fields = data.fields();
+    if sort {
+        fields.sort_by(|a, b| field::compare_keys(field_order, &a.key, &b.key));
+    }
+
+    menu_items.extend(fields.iter().map(|f| f.to_string()));
+    menu_items
+}
+
+/// Placeholder of the store menu, counting the fields added so far
+pub fn placeholder(added: usize) -> String {
+    match added {
+        0 => "Secret fields".to_string(),
+        added => format!("Secret fields ({} added)", added),
+    }
+}
+
+/// Interpret a selection from the store menu
+pub fn parse(selection: &str) -> Result<MenuAction> {
+    Ok(match selection {
+        ADD_FIELD_OPTION => MenuAction::AddField,
+        COMPLETE_OPTION => MenuAction::Complete,
+        SCAN_QR_OPTION => MenuAction::ScanQr,
+        FROM_COMMAND_OPTION => MenuAction::FromCommand,
+        FROM_CLIPBOARD_OPTION => MenuAction::FromClipboard,
+        TOGGLE_SUBMIT_OPTION => MenuAction::ToggleSubmit,
+        TOGGLE_SENSITIVE_OPTION => MenuAction::ToggleSensitive,
+        _ => MenuAction::EditField(Field::parse_from_display(selection)?),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_items_lists_options_before_sorted_fields() {
+        let mut data = Secret::new();
+        data.insert("username".to_string(), "john".to_string());
+        data.insert("password".to_string(), "secret".to_string());
+
+        assert_eq!(
+            items(&data, &[], true),
+            vec![
+                ADD_FIELD_OPTION,
+                COMPLETE_OPTION,
+                SCAN_QR_OPTION,
+                FROM_COMMAND_OPTION,
+                FROM_CLIPBOARD_OPTION,
+                TOGGLE_SUBMIT_OPTION,
+                TOGGLE_SENSITIVE_OPTION,
+                "password: ********",
+                "username: john",
+            ]
+        );
+
+        let order = vec!["username".to_string()];
+        assert_eq!(items(&data, &order, true)[7], "username: john");
+        assert_eq!(
+            items(&data, &[], false)[7..],
+            ["username: john", "password: ********"]
+        );
+    }
+
+    #[test]
+    fn test_placeholder() {
+        assert_eq!(placeholder(0), "Secret fields");
+        assert_eq!(placeholder(3), "Secret fields (3 added)");
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(parse(ADD_FIELD_OPTION).unwrap(), MenuAction::AddField);
+        assert_eq!(parse(COMPLETE_OPTION).unwrap(), MenuAction::Complete);
+        assert_eq!(parse(SCAN_QR_OPTION).unwrap(), MenuAction::ScanQr);
+        assert_eq!(parse(FROM_COMMAND_OPTION).unwrap(), MenuAction::FromCommand);
+        assert_eq!(
+            parse(FROM_CLIPBOARD_OPTION).unwrap(),
+            MenuAction::FromClipboard
+        );
+        assert_eq!(
+            parse(TOGGLE_SUBMIT_OPTION).unwrap(),
+            MenuAction::ToggleSubmit
+        );
+        assert_eq!(
+            parse(TOGGLE_SENSITIVE_OPTION).unwrap(),
+            MenuAction::ToggleSensitive
+        );
+        assert_eq!(
+            parse("username: john").unwrap(),
+            MenuAction::EditField(Field::new("username".to_string(), "john".to_string()))
+        );
+        assert!(parse("garbage").is_err());
+    }
+}
@@ -0,0 +1,23 @@
+use std::io::ErrorKind;
+use std::process::Command;
+
+/// Show a desktop notification with notify-send
+///
+/// Notifications are best effort: failures are reported on stderr but never
+/// fail the action being notified about. Never pass secret values here.
+pub fn send(summary: &str) {
+    let result = Command::new("notify-send")
+        .arg("--app-name")
+        .arg("fuzzel-secrets")
+        .arg(summary)
+        .status();
+
+    match result {
+        Ok(status) if status.success() => {}
+        Ok(_) => eprintln!("notify-send failed"),
+        Err(err) if err.kind() == ErrorKind::NotFound => {
+            eprintln!("notify-send not found in PATH; skipping notification")
+        }
+        Err(err) => eprintln!("Failed to execute notify-send: {}", err),
+    }
+}
@@ -0,0 +1,120 @@
+use crate::shell;
+use anyhow::{anyhow, bail, Context, Result};
+use percent_encoding::percent_decode_str;
+use url::Url;
+
+/// Field key used when storing a scanned TOTP seed
+pub const TOTP_FIELD: &str = "totp_secret";
+
+/// The relevant parts of an `otpauth://` URI
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OtpAuth {
+    pub secret: String,
+    pub issuer: Option<String>,
+    pub account: Option<String>,
+}
+
+impl OtpAuth {
+    /// Parse and validate an `otpauth://totp/...` or `otpauth://hotp/...` URI
+    pub fn parse(uri: &str) -> Result<OtpAuth> {
+        let url = Url::parse(uri.trim()).context("Scanned data is not a valid URI")?;
+
+        if url.scheme() != "otpauth" {
+            bail!("Scanned data is not an otpauth:// URI");
+        }
+        match url.host_str() {
+            Some("totp") | Some("hotp") => {}
+            _ => bail!("Unsupported otpauth type, expected totp or hotp"),
+        }
+
+        let query = |name: &str| {
+            url.query_pairs()
+                .find(|(key, _)| key == name)
+                .map(|(_, value)| value.into_owned())
+        };
+
+        let secret = query("secret")
+            .map(|secret| normalize_base32(&secret))
+            .filter(|secret| !secret.is_empty())
+            .ok_or_else(|| anyhow!("otpauth URI has no secret"))?;
+        if !is_base32(&secret) {
+            bail!("otpauth secret is not valid base32");
+        }
+
+        // The label is "issuer:account" or just "account", percent-encoded
+        let label = percent_decode_str(url.path().trim_start_matches('/'))
+            .decode_utf8()
+            .context("otpauth label is not valid UTF-8")?
+            .into_owned();
+        let (label_issuer, account) = match label.split_once(':') {
+            Some((issuer, account)) => (Some(issuer.trim().to_string()), account.trim()),
+            None => (None, label.trim()),
+        };
+
+        Ok(OtpAuth {
+            secret,
+            issuer: query("issuer").or(label_issuer).filter(|s| !s.is_empty()),
+            account: Some(account.to_string()).filter(|s| !s.is_empty()),
+        })
+    }
+}
+
+/// Uppercase and strip spaces and padding, as seeds are often grouped for readability
+fn normalize_base32(secret: &str) -> String {
+    secret
+        .chars()
+        .filter(|c| !c.is_whitespace() && *c != '=')
+        .map(|c| c.to_ascii_uppercase())
+        .collect()
+}
+
+fn is_base32(secret: &str) -> bool {
+    secret
+        .chars()
+        .all(|c| c.is_ascii_uppercase() || ('2'..='7').contains(&c))
+}
+
+/// Run the configured scanner command and parse the `otpauth://` URI it prints
+pub fn scan(command: &str) -> Result<OtpAuth> {
+    let result = shell::output(command).context("QR scanner command failed")?;
+
+    // Scanners may report several codes; use the first otpauth one
+    let uri = result
+        .lines()
+        .find(|line| line.trim_start().starts_with("otpauth://"))
+        .ok_or_else(|| anyhow!("No otpauth:// QR code found"))?;
+
+    OtpAuth::parse(uri)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_full_uri() {
+        let otp = OtpAuth::parse(
+            "otpauth://totp/ACME%20Co:john@example.com?secret=JBSWY3DPEHPK3PXP&issuer=ACME+Co",
+        )
+        .unwrap();
+        assert_eq!(otp.secret, "JBSWY3DPEHPK3PXP");
+        assert_eq!(otp.issuer.as_deref(), Some("ACME Co"));
+        assert_eq!(otp.account.as_deref(), Some("john@example.com"));
+    }
+
+    #[test]
+    fn test_parse_normalizes_secret() {
+        let otp = OtpAuth::parse("otpauth://totp/john?secret=jbsw%20y3dp%3D%3D").unwrap();
+        assert_eq!(otp.secret, "JBSWY3DP");
+        assert_eq!(otp.issuer, None);
+        assert_eq!(otp.account.as_deref(), Some("john"));
+    }
+
+    #[test]
+    fn test_parse_rejects_invalid() {
+        assert!(OtpAuth::parse("https://example.com/?secret=JBSWY3DP").is_err());
+        assert!(OtpAuth::parse("otpauth://foo/john?secret=JBSWY3DP").is_err());
+        assert!(OtpAuth::parse("otpauth://totp/john").is_err());
+        assert!(OtpAuth::parse("otpauth://totp/john?secret=NOT-BASE32!").is_err());
+    }
+}
@@ -0,0 +1,173 @@
+use crate::fuzzel::{self, Cancelled};
+use anyhow::{anyhow, bail, Context, Result};
+use std::io::{BufRead, BufReader, ErrorKind, Write};
+use std::process::{Command, Stdio};
+
+/// Assuan error code pinentry reports when the user cancels
+const CANCELLED_CODE: &str = "83886179";
+
+/// Error returned when pinentry isn't installed, so callers can fall back
+#[derive(Debug)]
+pub struct NotInstalled;
+
+impl std::fmt::Display for NotInstalled {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "pinentry not found in PATH")
+    }
+}
+
+impl std::error::Error for NotInstalled {}
+
+/// Ask for a secret value with pinentry
+pub fn request_pin(description: &str, prompt: &str) -> Result<String> {
+    fuzzel::require_interactive()?;
+    let mut child = match Command::new("pinentry")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+    {
+        Ok(child) => child,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Err(NotInstalled.into()),
+        Err(err) => return Err(err).context("Failed to spawn pinentry"),
+    };
+
+    let stdin = child
+        .stdin
+        .take()
+        .context("Failed to open pinentry stdin")?;
+    let stdout = child
+        .stdout
+        .take()
+        .context("Failed to open pinentry stdout")?;
+    let result = get_pin(&mut BufReader::new(stdout), stdin, description, prompt);
+
+    child.wait().context("Failed to wait for pinentry")?;
+    result
+}
+
+/// Speak enough of the Assuan protocol to set the texts and read back a pin
+fn get_pin<R: BufRead, W: Write>(
+    reader: &mut R,
+    mut writer: W,
+    description: &str,
+    prompt: &str,
+) -> Result<String> {
+    read_response(reader)?;
+
+    for command in [
+        format!("SETDESC {}", escape(description)),
+        format!("SETPROMPT {}", escape(prompt)),
+    ] {
+        send(&mut writer, &command)?;
+        read_response(reader)?;
+    }
+
+    send(&mut writer, "GETPIN")?;
+    let pin = read_response(reader)?.unwrap_or_default();
+
+    // Best effort, pinentry exits when stdin closes anyway
+    let _ = send(&mut writer, "BYE");
+
+    Ok(pin)
+}
+
+fn send<W: Write>(writer: &mut W, command: &str) -> Result<()> {
+    writeln!(writer, "{}", command).context("Failed to write to pinentry")?;
+    writer.flush().context("Failed to write to pinentry")
+}
+
+/// Read lines until OK or ERR, returning the data sent with `D` lines
+fn read_response<R: BufRead>(reader: &mut R) -> Result<Option<String>> {
+    let mut data: Option<String> = None;
+
+    loop {
+        let mut line = String::new();
+        if reader
+            .read_line(&mut line)
+            .context("Failed to read from pinentry")?
+            == 0
+        {
+            bail!("pinentry closed the connection unexpectedly");
+        }
+        let line = line.trim_end_matches(['\r', '\n']);
+
+        if line == "OK" || line.starts_with("OK ") {
+            return Ok(data);
+        } else if let Some(chunk) = line.strip_prefix("D ") {
+            data.get_or_insert_with(String::new)
+                .push_str(&unescape(chunk)?);
+        } else if let Some(error) = line.strip_prefix("ERR ") {
+            if error.split_whitespace().next() == Some(CANCELLED_CODE) {
+                return Err(Cancelled.into());
+            }
+            bail!("pinentry error: {}", error);
+        }
+        // Status ("S") and comment ("#") lines are ignored
+    }
+}
+
+/// Percent-escape the characters Assuan doesn't allow in a line
+fn escape(text: &str) -> String {
+    text.replace('%', "%25")
+        .replace('\n', "%0A")
+        .replace('\r', "%0D")
+}
+
+fn unescape(text: &str) -> Result<String> {
+    let mut bytes = Vec::with_capacity(text.len());
+    let mut chars = text.bytes();
+
+    while let Some(byte) = chars.next() {
+        if byte == b'%' {
+            let hex = [chars.next(), chars.next()];
+            let hex = match hex {
+                [Some(a), Some(b)] => [a, b],
+                _ => bail!("Invalid escape in pinentry data"),
+            };
+            let hex = std::str::from_utf8(&hex).context("Invalid escape in pinentry data")?;
+            bytes.push(
+                u8::from_str_radix(hex, 16)
+                    .map_err(|_| anyhow!("Invalid escape in pinentry data"))?,
+            );
+        } else {
+            bytes.push(byte);
+        }
+    }
+
+    String::from_utf8(bytes).context("pinentry data is not UTF-8")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_get_pin() {
+        let mut reader = Cursor::new(
+            "OK Pleased to meet you\nOK\nOK\nS PASSPHRASE_INFO x\nD hunter%252%0A\nOK\n",
+        );
+        let mut written = Vec::new();
+
+        let pin = get_pin(&mut reader, &mut written, "Value for\n'pass'", "Value:").unwrap();
+        assert_eq!(pin, "hunter%2\n");
+        assert_eq!(
+            String::from_utf8(written).unwrap(),
+            "SETDESC Value for%0A'pass'\nSETPROMPT Value:\nGETPIN\nBYE\n"
+        );
+    }
+
+    #[test]
+    fn test_get_pin_empty() {
+        let mut reader = Cursor::new("OK\nOK\nOK\nOK\n");
+        let pin = get_pin(&mut reader, Vec::new(), "desc", "prompt").unwrap();
+        assert_eq!(pin, "");
+    }
+
+    #[test]
+    fn test_get_pin_cancelled() {
+        let mut reader = Cursor::new("OK\nOK\nOK\nERR 83886179 Operation cancelled <Pinentry>\n");
+        let err = get_pin(&mut reader, Vec::new(), "desc", "prompt").unwrap_err();
+        assert!(err.is::<Cancelled>());
+    }
+}
@@ -0,0 +1,187 @@
+use crate::secret::Secret;
+use crate::secrets::NotFound;
+use anyhow::{bail, Context, Result};
+use std::future::Future;
+
+/// Prefix of values referring to a field of another secret, like
+/// `@ref:work/aws/password`
+pub const REFERENCE_PREFIX: &str = "@ref:";
+
+/// References followed at most from one value, unless configured otherwise
+pub const DEFAULT_MAX_DEPTH: usize = 5;
+
+/// Split a reference into the label and key of the field it refers to
+///
+/// Labels may contain `/`, so the key is named after the last one.
+fn parse_reference(value: &str) -> Result<Option<(&str, &str)>> {
+    let Some(target) = value.strip_prefix(REFERENCE_PREFIX) else {
+        return Ok(None);
+    };
+    match target.rsplit_once('/') {
+        Some((label, key)) if !label.is_empty() && !key.is_empty() => Ok(Some((label, key))),
+        _ => bail!(
+            "Invalid reference '{}', expected {}label/field",
+            value,
+            REFERENCE_PREFIX
+        ),
+    }
+}
+
+/// A value with the references leading to it followed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Resolved {
+    pub value: String,
+    /// Whether any field referred to on the way is sensitive, so a value
+    /// shared through a plain field is still handled as a sensitive one
+    pub sensitive: bool,
+}
+
+/// The value a field stands for, following references to fields of other
+/// secrets
+///
+/// Values that aren't references are returned as they are, and not
+/// sensitive by themselves. Secrets are loaded with `lookup`, which fails
+/// with [`NotFound`] for secrets that don't exist. Following more than
+/// `max_depth` references, or a reference back to a field already visited,
+/// is an error rather than an endless loop.
+pub async fn resolve<F, Fut>(reference: &str, max_depth: usize, mut lookup: F) -> Result<Resolved>
+where
+    F: FnMut(String) -> Fut,
+    Fut: Future<Output = Result<Secret>>,
+{
+    let mut visited: Vec<String> = Vec::new();
+    let mut value = reference.to_string();
+    let mut sensitive = false;
+    while let Some((label, key)) = parse_reference(&value)? {
+        let target = format!("{}/{}", label, key);
+        if visited.contains(&target) {
+            visited.push(target);
+            bail!("Reference cycle: {}", visited.join(" -> "));
+        }
+        if visited.len() == max_depth {
+            bail!(
+                "More than {} references to follow from '{}'",
+                max_depth,
+                reference
+            );
+        }
+
+        let context = || format!("Failed to resolve reference to '{}'", target);
+        let data = lookup(label.to_string()).await.with_context(context)?;
+        sensitive |= data.is_sensitive(key);
+        let key = key.to_string();
+        value = data
+            .get_value(&key)
+            .ok_or(NotFound::Field(key))
+            .with_context(context)?
+            .to_string();
+        visited.push(target);
+    }
+    Ok(Resolved { value, sensitive })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::future::{ready, Ready};
+
+    fn secret(fields: &[(&str, &str)]) -> Secret {
+        let mut data = Secret::new();
+        for (key, value) in fields {
+            data.insert(key.to_string(), value.to_string());
+        }
+        data
+    }
+
+    fn lookup<'a>(
+        secrets: &'a HashMap<&str, Secret>,
+    ) -> impl FnMut(String) -> Ready<Result<Secret>> + 'a {
+        |label| {
+            ready(match secrets.get(label.as_str()) {
+                Some(data) => Ok(data.clone()),
+                None => Err(NotFound::Secret(label).into()),
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_resolve_plain_value() {
+        let secrets = HashMap::new();
+        let resolved = resolve("hunter2", DEFAULT_MAX_DEPTH, lookup(&secrets)).await;
+        assert_eq!(
+            resolved.unwrap(),
+            Resolved {
+                value: "hunter2".to_string(),
+                sensitive: false
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_resolve_multiple_hops() {
+        let secrets = HashMap::from([
+            ("jira", secret(&[("password", "@ref:work/ldap/password")])),
+            ("work/ldap", secret(&[("password", "@ref:root/pw")])),
+            ("root", secret(&[("pw", "hunter2")])),
+        ]);
+        let resolved = resolve("@ref:jira/password", DEFAULT_MAX_DEPTH, lookup(&secrets)).await;
+        assert_eq!(resolved.unwrap().value, "hunter2");
+
+        let err = resolve("@ref:jira/password", 2, lookup(&secrets))
+            .await
+            .unwrap_err();
+        assert!(err.to_string().starts_with("More than 2 references"));
+    }
+
+    #[tokio::test]
+    async fn test_resolve_keeps_sensitivity_of_targets() {
+        let secrets = HashMap::from([
+            (
+                "ldap",
+                secret(&[("password", "hunter2"), ("username", "john")]),
+            ),
+            ("alias", secret(&[("username", "@ref:ldap/password")])),
+        ]);
+        for (reference, sensitive) in [
+            ("@ref:ldap/password", true),
+            ("@ref:ldap/username", false),
+            // A plain field on the way doesn't make the value less sensitive
+            ("@ref:alias/username", true),
+        ] {
+            let resolved = resolve(reference, DEFAULT_MAX_DEPTH, lookup(&secrets)).await;
+            assert_eq!(resolved.unwrap().sensitive, sensitive, "{}", reference);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_resolve_detects_cycles() {
+        let secrets = HashMap::from([
+            ("a", secret(&[("password", "@ref:b/password")])),
+            ("b", secret(&[("password", "@ref:a/password")])),
+        ]);
+        let err = resolve("@ref:a/password", 100, lookup(&secrets))
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Reference cycle: a/password -> b/password -> a/password"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_resolve_dangling_references() {
+        let secrets = HashMap::from([("a", secret(&[("password", "hunter2")]))]);
+        for reference in ["@ref:a/token", "@ref:missing/password"] {
+            let err = resolve(reference, DEFAULT_MAX_DEPTH, lookup(&secrets))
+                .await
+                .unwrap_err();
+            assert!(err.chain().any(|cause| cause.is::<NotFound>()));
+        }
+        assert!(
+            resolve("@ref:password", DEFAULT_MAX_DEPTH, lookup(&secrets))
+                .await
+                .is_err()
+        );
+    }
+}
@@ -1,19 +1,192 @@
 use crate::field::Field;
+use crate::timestamp;
+use anyhow::{bail, Result};
+use indexmap::IndexMap;
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 use std::collections::HashMap;
+use std::fmt;
+
+/// Metadata key listing fields that are sensitive despite their key
+const SENSITIVE_METADATA: &str = "sensitive";
+/// Metadata key listing fields that aren't sensitive despite their key
+const INSENSITIVE_METADATA: &str = "insensitive";
+
+/// Field naming a freedesktop icon, e.g. `web-browser`, which is always stored
+/// as an item attribute so pickers can show it without decrypting
+pub const ICON_FIELD: &str = "icon";
+
+/// Prefix of the keys holding metadata in payloads before version 2, which
+/// mixed metadata with the fields
+const METADATA_PREFIX: &str = "__";
+/// Metadata key listing the fields that are submitted with Enter after typing
+const SUBMIT_METADATA: &str = "submit";
+/// Metadata key holding the RFC 3339 time after which the secret should be rotated
+const EXPIRES_AT_METADATA: &str = "expires_at";
+/// Metadata key prefix holding the value a field had before it was rotated
+const PREVIOUS_VALUE_METADATA: &str = "previous_value:";
+/// Metadata key prefix holding when a field was rotated, in seconds since the epoch
+const PREVIOUS_TIME_METADATA: &str = "previous_time:";
+/// Metadata key prefix of previous values before version 3, which couldn't be
+/// told apart from the rotation times of keys starting with `at_`
+const LEGACY_PREVIOUS_METADATA: &str = "previous_";
+/// Metadata key prefix of rotation times before version 3
+const LEGACY_PREVIOUS_AT_METADATA: &str = "previous_at_";
+/// Metadata key holding the version of version 1 payloads, see [`Secret::upgrade`]
+const SCHEMA_VERSION_METADATA: &str = "schema_version";
+/// Attributes set by `secrets::store` itself, which fields must not override
+pub(crate) const RESERVED_ATTRIBUTES: &[&str] = &["label", "xdg:schema"];
+
+/// Version of the payload layout written by this version of fuzzel-secrets
+pub const SCHEMA_VERSION: u32 = 3;
+
+/// Keys of the fields that differ between two secrets, see [`Secret::diff`]
+///
+/// Only keys are recorded, so a diff is always safe to print.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SecretDiff {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    pub changed: Vec<String>,
+}
+
+impl SecretDiff {
+    /// Check if no field differs
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+impl fmt::Display for SecretDiff {
+    /// Formats like "changed: password; added: otp", or "no changes"
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_empty() {
+            return write!(f, "no changes");
+        }
+
+        let parts: Vec<String> = [
+            ("changed", &self.changed),
+            ("added", &self.added),
+            ("removed", &self.removed),
+        ]
+        .into_iter()
+        .filter(|(_, keys)| !keys.is_empty())
+        .map(|(kind, keys)| format!("{}: {}", kind, keys.join(", ")))
+        .collect();
+        write!(f, "{}", parts.join("; "))
+    }
+}
 
 /// Data structure for storing secret details, encapsulating fields
+///
+/// Metadata is kept apart from the fields, and is not part of the fields
+/// returned by `iter`, `keys`, `len` and friends. Secrets are serialized as
+/// `{"schema_version": 3, "fields": {...}, "meta": {...}}`; flat payloads of
+/// earlier versions are still read. Metadata values are JSON values, e.g.
+/// arrays for lists of field keys.
+///
+/// Fields keep the order they were inserted in, also when serialized. Two
+/// secrets are equal when they hold the same fields and metadata, regardless of
+/// that order.
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(try_from = "StoredSecret", into = "StoredSecret")]
 pub struct Secret {
-    #[serde(flatten)]
-    fields: HashMap<String, String>,
+    fields: IndexMap<String, String>,
+    meta: HashMap<String, Value>,
+    /// Version of the payload the secret was read from
+    version: u32,
+}
+
+/// Layouts of serialized secrets
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum StoredSecret {
+    /// User fields and metadata in separate objects, since version 2
+    Split {
+        schema_version: u32,
+        fields: IndexMap<String, String>,
+        #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+        meta: HashMap<String, Value>,
+    },
+    /// User fields and metadata under `__`-prefixed keys in one object, before
+    /// version 2
+    ///
+    /// Field values are strings, so a flat payload never matches the split
+    /// layout, even with a field named `fields`.
+    Flat(IndexMap<String, String>),
 }
 
+impl TryFrom<StoredSecret> for Secret {
+    type Error = String;
+
+    fn try_from(stored: StoredSecret) -> Result<Self, Self::Error> {
+        match stored {
+            StoredSecret::Split {
+                schema_version,
+                fields,
+                meta,
+            } => Ok(Secret {
+                fields,
+                meta,
+                version: schema_version,
+            }),
+            StoredSecret::Flat(entries) => {
+                let (meta, fields): (IndexMap<_, _>, IndexMap<_, _>) = entries
+                    .into_iter()
+                    .partition(|(key, _)| key.starts_with(METADATA_PREFIX));
+                let mut meta: HashMap<String, Value> = meta
+                    .into_iter()
+                    .map(|(key, value)| {
+                        (
+                            key[METADATA_PREFIX.len()..].to_string(),
+                            Value::String(value),
+                        )
+                    })
+                    .collect();
+                let version = match meta.remove(SCHEMA_VERSION_METADATA) {
+                    Some(version) => version
+                        .as_str()
+                        .and_then(|version| version.parse().ok())
+                        .ok_or_else(|| format!("invalid schema version {}", version))?,
+                    None => 0,
+                };
+                Ok(Secret {
+                    fields,
+                    meta,
+                    version,
+                })
+            }
+        }
+    }
+}
+
+impl From<Secret> for StoredSecret {
+    /// Always writes the current layout, which is what secrets are kept in
+    /// once read
+    fn from(secret: Secret) -> Self {
+        StoredSecret::Split {
+            schema_version: SCHEMA_VERSION,
+            fields: secret.fields,
+            meta: secret.meta,
+        }
+    }
+}
+
+impl PartialEq for Secret {
+    fn eq(&self, other: &Self) -> bool {
+        self.fields == other.fields && self.meta == other.meta
+    }
+}
+
+impl Eq for Secret {}
+
 impl Secret {
     /// Create a new empty SecretData
     pub fn new() -> Self {
         Self {
-            fields: HashMap::new(),
+            fields: IndexMap::new(),
+            meta: HashMap::new(),
+            version: SCHEMA_VERSION,
         }
     }
 
@@ -24,15 +197,28 @@ impl Secret {
 
     /// Get a field by key
     pub fn get(&self, key: &str) -> Option<Field> {
-        self.fields
-            .get(key)
-            .map(|value| Field::new(key.to_string(), value.clone()))
+        self.fields.get(key).map(|value| self.field(key, value))
+    }
+
+    /// Build a field of this secret, carrying its sensitivity override
+    fn field(&self, key: &str, value: &str) -> Field {
+        Field::with_sensitivity(
+            key.to_string(),
+            value.to_string(),
+            self.sensitivity_override(key),
+        )
+    }
+
+    /// Get a field's value by key without cloning it
+    pub fn get_value(&self, key: &str) -> Option<&str> {
+        self.fields.get(key).map(String::as_str)
     }
 
     /// Remove a field by key
     pub fn remove(&mut self, key: &str) -> Option<Field> {
+        // Shifting keeps the order of the remaining fields
         self.fields
-            .remove(key)
+            .shift_remove(key)
             .map(|value| Field::new(key.to_string(), value.clone()))
     }
 
@@ -48,14 +234,12 @@ impl Secret {
 
     /// Check if empty
     pub fn is_empty(&self) -> bool {
-        self.fields.is_empty()
+        self.len() == 0
     }
 
     /// Iterate over all fields
     pub fn iter(&self) -> impl Iterator<Item = Field> + '_ {
-        self.fields
-            .iter()
-            .map(|(k, v)| Field::new(k.clone(), v.clone()))
+        self.fields.iter().map(|(k, v)| self.field(k, v))
     }
 
     /// Get all field keys
@@ -67,6 +251,337 @@ impl Secret {
     pub fn fields(&self) -> Vec<Field> {
         self.iter().collect()
     }
+
+    /// Keep only the fields matching the predicate
+    ///
+    /// Metadata is kept, except the previous values of the dropped fields,
+    /// which would otherwise still reveal them.
+    pub fn filter<F: Fn(&Field) -> bool>(mut self, keep: F) -> Secret {
+        let dropped: Vec<String> = self
+            .iter()
+            .filter(|field| !keep(field))
+            .map(|field| field.key)
+            .collect();
+        self.drop_fields(&dropped);
+        self
+    }
+
+    /// Remove fields together with their previous values
+    fn drop_fields(&mut self, keys: &[String]) {
+        self.fields.retain(|key, _| !keys.contains(key));
+        for key in keys {
+            self.remove_previous(key);
+        }
+    }
+
+    /// Compare the fields of this secret, as edited, with `other` as it was
+    ///
+    /// Keys are sorted. Metadata is ignored.
+    pub fn diff(&self, other: &Secret) -> SecretDiff {
+        let mut diff = SecretDiff::default();
+        for key in self.keys() {
+            match other.get_value(key) {
+                None => diff.added.push(key.clone()),
+                Some(value) if self.get_value(key) != Some(value) => diff.changed.push(key.clone()),
+                Some(_) => {}
+            }
+        }
+        diff.removed = other
+            .keys()
+            .filter(|key| !self.contains_key(key))
+            .cloned()
+            .collect();
+
+        diff.added.sort();
+        diff.removed.sort();
+        diff.changed.sort();
+        diff
+    }
+
+    /// Keep the current value of a field as its previous value before rotating it
+    pub fn set_previous(&mut self, key: &str, rotated_at: i64) {
+        let Some(value) = self.get_value(key).map(str::to_string) else {
+            return;
+        };
+        self.set_meta(&format!("{}{}", PREVIOUS_VALUE_METADATA, key), value);
+        self.set_meta(
+            &format!("{}{}", PREVIOUS_TIME_METADATA, key),
+            rotated_at.to_string(),
+        );
+    }
+
+    /// The value a field had before it was last rotated, and when that was
+    pub fn previous(&self, key: &str) -> Option<(&str, i64)> {
+        let value = self.meta(&format!("{}{}", PREVIOUS_VALUE_METADATA, key))?;
+        let rotated_at = self
+            .meta(&format!("{}{}", PREVIOUS_TIME_METADATA, key))?
+            .parse()
+            .ok()?;
+        Some((value, rotated_at))
+    }
+
+    /// Forget the previous value of a field
+    pub fn remove_previous(&mut self, key: &str) {
+        self.remove_meta(&format!("{}{}", PREVIOUS_VALUE_METADATA, key));
+        self.remove_meta(&format!("{}{}", PREVIOUS_TIME_METADATA, key));
+    }
+
+    /// Keys of the fields with a previous value
+    pub fn previous_keys(&self) -> Vec<String> {
+        let mut keys: Vec<String> = self
+            .meta
+            .keys()
+            .filter_map(|key| key.strip_prefix(PREVIOUS_VALUE_METADATA))
+            .map(str::to_string)
+            .collect();
+        keys.sort();
+        keys
+    }
+
+    /// Drop previous values kept for longer than the retention period, or
+    /// without a valid rotation time. Returns whether any were dropped.
+    pub fn prune_previous(&mut self, now: i64, retention_seconds: i64) -> bool {
+        let expired: Vec<String> = self
+            .previous_keys()
+            .into_iter()
+            .filter(|key| {
+                self.previous(key)
+                    .is_none_or(|(_, rotated_at)| now - rotated_at > retention_seconds)
+            })
+            .collect();
+
+        for key in &expired {
+            self.remove_previous(key);
+        }
+        !expired.is_empty()
+    }
+
+    /// Drop all fields whose key isn't listed. Metadata is kept, except the
+    /// previous values of the dropped fields, as with [`Secret::filter`].
+    pub fn retain_keys(&mut self, keys: &[String]) {
+        let dropped: Vec<String> = self
+            .keys()
+            .filter(|key| !keys.contains(key))
+            .cloned()
+            .collect();
+        self.drop_fields(&dropped);
+    }
+
+    /// Turn the given fields into Secret Service attributes, so items can be
+    /// searched by them without decrypting
+    ///
+    /// Attributes are stored unencrypted, so sensitive fields, metadata and
+    /// reserved attribute names are never included.
+    pub fn into_attributes(self, keys: &[String]) -> HashMap<String, String> {
+        self.fields
+            .iter()
+            .filter(|(key, _)| {
+                keys.contains(key)
+                    && !self.is_sensitive(key)
+                    && !RESERVED_ATTRIBUTES.contains(&key.as_str())
+            })
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect()
+    }
+
+    /// Add the fields of another secret that this one doesn't have
+    ///
+    /// Values already present are kept. Returns the keys whose values differed
+    /// and were therefore not taken from `other`.
+    pub fn merge(&mut self, other: Secret) -> Vec<String> {
+        let mut conflicts = Vec::new();
+
+        for field in other.iter() {
+            match self.get_value(&field.key) {
+                None => self.insert(field.key, field.value),
+                Some(value) if value != field.value => conflicts.push(field.key),
+                Some(_) => {}
+            }
+        }
+
+        conflicts.sort();
+        conflicts
+    }
+
+    /// Get a metadata value holding text
+    pub fn meta(&self, key: &str) -> Option<&str> {
+        self.meta.get(key).and_then(Value::as_str)
+    }
+
+    /// Set a metadata value holding text
+    pub fn set_meta(&mut self, key: &str, value: String) {
+        self.meta.insert(key.to_string(), Value::String(value));
+    }
+
+    /// Remove a metadata value
+    pub fn remove_meta(&mut self, key: &str) -> Option<Value> {
+        self.meta.remove(key)
+    }
+
+    /// Check if a field should be submitted with Enter after typing it
+    pub fn submits(&self, key: &str) -> bool {
+        self.meta_keys(SUBMIT_METADATA).iter().any(|k| k == key)
+    }
+
+    /// Set whether a field should be submitted with Enter after typing it
+    pub fn set_submits(&mut self, key: &str, submit: bool) {
+        self.set_meta_key(SUBMIT_METADATA, key, submit);
+    }
+
+    /// Keys listed in a metadata array
+    fn meta_keys(&self, meta: &str) -> Vec<String> {
+        match self.meta.get(meta) {
+            Some(Value::Array(keys)) => keys
+                .iter()
+                .filter_map(Value::as_str)
+                .map(str::to_string)
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Add or remove a key in a metadata array
+    fn set_meta_key(&mut self, meta: &str, key: &str, listed: bool) {
+        let mut keys = self.meta_keys(meta);
+        keys.retain(|k| k != key);
+        if listed {
+            keys.push(key.to_string());
+        }
+
+        if keys.is_empty() {
+            self.remove_meta(meta);
+        } else {
+            keys.sort();
+            self.meta.insert(meta.to_string(), Value::from(keys));
+        }
+    }
+
+    /// The sensitivity set explicitly for a field, overriding its key
+    pub fn sensitivity_override(&self, key: &str) -> Option<bool> {
+        if self.meta_keys(SENSITIVE_METADATA).iter().any(|k| k == key) {
+            Some(true)
+        } else if self
+            .meta_keys(INSENSITIVE_METADATA)
+            .iter()
+            .any(|k| k == key)
+        {
+            Some(false)
+        } else {
+            None
+        }
+    }
+
+    /// Set or clear the sensitivity override of a field
+    pub fn set_sensitivity_override(&mut self, key: &str, sensitive: Option<bool>) {
+        self.set_meta_key(SENSITIVE_METADATA, key, sensitive == Some(true));
+        self.set_meta_key(INSENSITIVE_METADATA, key, sensitive == Some(false));
+    }
+
+    /// Check if a field is sensitive, by its override or else by its key
+    pub fn is_sensitive(&self, key: &str) -> bool {
+        self.sensitivity_override(key)
+            .unwrap_or_else(|| Field::is_key_sensitive(key))
+    }
+
+    /// Version of the payload layout this secret was read from
+    ///
+    /// Payloads from before versioning have none, which is version 0.
+    pub fn schema_version(&self) -> u32 {
+        self.version
+    }
+
+    /// Bring a payload up to [`SCHEMA_VERSION`] in memory
+    ///
+    /// Each version is upgraded to the next until the current one is reached,
+    /// so older secrets keep working when metadata conventions change. Version
+    /// 1 only introduced the version itself: version 0 payloads already hold
+    /// bare fields with `__`-prefixed metadata. Version 2 moved the metadata
+    /// apart from the fields, which happens while deserializing. Payloads of a
+    /// newer version are refused, since storing them again would drop what
+    /// isn't understood. Version 3 renamed the metadata of previous values,
+    /// see [`Secret::rename_legacy_previous`], and turned the comma-separated
+    /// lists of keys into arrays, so keys may contain commas.
+    pub fn upgrade(mut self) -> Result<Secret> {
+        let version = self.version;
+        if version > SCHEMA_VERSION {
+            bail!(
+                "Schema version {} is newer than the supported version {}; upgrade fuzzel-secrets",
+                version,
+                SCHEMA_VERSION
+            );
+        }
+
+        if version < 3 {
+            self.rename_legacy_previous();
+            for meta in [SUBMIT_METADATA, SENSITIVE_METADATA, INSENSITIVE_METADATA] {
+                if let Some(Value::String(keys)) = self.meta.get(meta) {
+                    let keys: Vec<&str> = keys.split(',').filter(|k| !k.is_empty()).collect();
+                    self.meta.insert(meta.to_string(), Value::from(keys));
+                }
+            }
+        }
+
+        self.version = SCHEMA_VERSION;
+        Ok(self)
+    }
+
+    /// Move previous values from `previous_<key>` and `previous_at_<key>` to
+    /// their unambiguous names
+    ///
+    /// An entry is taken as the previous value of a key when a valid rotation
+    /// time exists for the same key. Entries that can't be paired are dropped,
+    /// as they would be by [`Secret::prune_previous`].
+    fn rename_legacy_previous(&mut self) {
+        let mut names: Vec<String> = self
+            .meta
+            .keys()
+            .filter(|name| name.starts_with(LEGACY_PREVIOUS_METADATA))
+            .cloned()
+            .collect();
+        names.sort();
+
+        for name in &names {
+            let key = &name[LEGACY_PREVIOUS_METADATA.len()..];
+            let time_name = format!("{}{}", LEGACY_PREVIOUS_AT_METADATA, key);
+            let paired = self.meta.contains_key(name)
+                && self
+                    .meta(&time_name)
+                    .is_some_and(|time| time.parse::<i64>().is_ok());
+            if !paired {
+                continue;
+            }
+            let value = self.meta.remove(name).unwrap();
+            let time = self.meta.remove(&time_name).unwrap();
+            self.meta
+                .insert(format!("{}{}", PREVIOUS_VALUE_METADATA, key), value);
+            self.meta
+                .insert(format!("{}{}", PREVIOUS_TIME_METADATA, key), time);
+        }
+
+        for name in &names {
+            self.meta.remove(name);
+        }
+    }
+
+    /// Expiry time in seconds since the Unix epoch
+    ///
+    /// A malformed expiry is treated as no expiry.
+    pub fn expires_at(&self) -> Option<i64> {
+        self.meta(EXPIRES_AT_METADATA)
+            .and_then(timestamp::parse_rfc3339)
+    }
+
+    /// Set or clear the expiry time, in seconds since the Unix epoch
+    pub fn set_expires_at(&mut self, expires_at: Option<i64>) {
+        match expires_at {
+            Some(expires_at) => {
+                self.set_meta(EXPIRES_AT_METADATA, timestamp::format_rfc3339(expires_at))
+            }
+            None => {
+                self.remove_meta(EXPIRES_AT_METADATA);
+            }
+        }
+    }
 }
 
 impl Default for Secret {
@@ -105,6 +620,128 @@ mod tests {
         assert_eq!(field.value, "john");
     }
 
+    #[test]
+    fn test_get_value() {
+        let mut data = Secret::new();
+        data.insert("username".to_string(), "john".to_string());
+
+        assert_eq!(data.get_value("username"), Some("john"));
+        assert_eq!(data.get_value("password"), None);
+    }
+
+    #[test]
+    fn test_serde_keeps_surrounding_whitespace() {
+        let mut data = Secret::new();
+        data.insert("token".to_string(), "abc ".to_string());
+        data.insert("passphrase".to_string(), " two words ".to_string());
+
+        let json = serde_json::to_string(&data).unwrap();
+        let parsed: Secret = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed.get_value("token"), Some("abc "));
+        assert_eq!(parsed.get_value("passphrase"), Some(" two words "));
+    }
+
+    fn parse(json: &str) -> Secret {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn test_upgrade_bare_fields() {
+        let v0 = parse(r#"{"username": "john", "__submit": "username"}"#);
+        assert_eq!(v0.schema_version(), 0);
+        assert_eq!(v0.keys().collect::<Vec<_>>(), vec!["username"]);
+
+        let upgraded = v0.clone().upgrade().unwrap();
+        assert_eq!(upgraded.schema_version(), SCHEMA_VERSION);
+        assert_eq!(upgraded.fields, v0.fields);
+        assert!(upgraded.submits("username"));
+
+        let json = serde_json::to_string(&upgraded).unwrap();
+        assert_eq!(parse(&json).upgrade().unwrap(), upgraded);
+    }
+
+    #[test]
+    fn test_flat_version_1_payload() {
+        let v1 =
+            parse(r#"{"password": "x", "__schema_version": "1", "__insensitive": "password"}"#);
+        assert_eq!(v1.schema_version(), 1);
+        assert_eq!(v1.len(), 1);
+        assert_eq!(v1.meta(SCHEMA_VERSION_METADATA), None);
+        let upgraded = v1.upgrade().unwrap();
+        assert_eq!(upgraded.sensitivity_override("password"), Some(false));
+        assert_eq!(upgraded.sensitivity_override("x"), None);
+
+        assert!(serde_json::from_str::<Secret>(r#"{"__schema_version": "one"}"#).is_err());
+    }
+
+    #[test]
+    fn test_serializes_fields_apart_from_metadata() {
+        let mut data = Secret::new();
+        data.insert("username".to_string(), "john".to_string());
+        data.insert("__notes".to_string(), "not metadata".to_string());
+        data.set_submits("username", true);
+
+        let json = serde_json::to_value(&data).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "schema_version": SCHEMA_VERSION,
+                "fields": {"username": "john", "__notes": "not metadata"},
+                "meta": {"submit": ["username"]},
+            })
+        );
+
+        let parsed: Secret = serde_json::from_value(json).unwrap();
+        assert_eq!(parsed, data);
+        assert_eq!(parsed.len(), 2);
+        assert!(parsed.submits("username"));
+    }
+
+    #[test]
+    fn test_metadata_is_optional_in_split_layout() {
+        let data = parse(r#"{"schema_version": 2, "fields": {"username": "john"}}"#);
+        assert_eq!(data.get_value("username"), Some("john"));
+        assert_eq!(
+            serde_json::to_value(Secret::new()).unwrap(),
+            serde_json::json!({"schema_version": SCHEMA_VERSION, "fields": {}})
+        );
+    }
+
+    #[test]
+    fn test_flat_payload_with_field_named_fields() {
+        let data = parse(r#"{"fields": "a, b", "schema_version": "3"}"#);
+        assert_eq!(data.schema_version(), 0);
+        assert_eq!(data.get_value("fields"), Some("a, b"));
+        assert_eq!(data.get_value("schema_version"), Some("3"));
+    }
+
+    #[test]
+    fn test_upgrade_refuses_newer_versions() {
+        let newer = format!(
+            r#"{{"schema_version": {}, "fields": {{}}}}"#,
+            SCHEMA_VERSION + 1
+        );
+        assert!(parse(&newer).upgrade().is_err());
+    }
+
+    #[test]
+    fn test_fields_keep_insertion_order() {
+        let mut data = Secret::new();
+        for key in ["username", "password", "url", "api_key"] {
+            data.insert(key.to_string(), "x".to_string());
+        }
+        data.insert("password".to_string(), "changed".to_string());
+        data.remove("url");
+
+        let keys = |data: &Secret| data.keys().cloned().collect::<Vec<_>>();
+        assert_eq!(keys(&data), vec!["username", "password", "api_key"]);
+
+        let parsed = parse(&serde_json::to_string(&data).unwrap());
+        assert_eq!(keys(&parsed), keys(&data));
+        let flat = parse(r#"{"url": "x", "__submit": "url", "password": "y"}"#);
+        assert_eq!(keys(&flat), vec!["url", "password"]);
+    }
+
     #[test]
     fn test_remove() {
         let mut data = Secret::new();
@@ -139,4 +776,287 @@ mod tests {
         assert!(keys.contains(&"key1".to_string()));
         assert!(keys.contains(&"key2".to_string()));
     }
+
+    #[test]
+    fn test_metadata_hidden_from_fields() {
+        let mut data = Secret::new();
+        data.insert("username".to_string(), "john".to_string());
+        data.set_meta("note", "internal".to_string());
+
+        assert_eq!(data.len(), 1);
+        assert_eq!(data.keys().collect::<Vec<_>>(), vec!["username"]);
+        assert_eq!(data.meta("note"), Some("internal"));
+    }
+
+    #[test]
+    fn test_submits() {
+        let mut data = Secret::new();
+        data.insert("password".to_string(), "secret".to_string());
+        assert!(!data.submits("password"));
+
+        data.set_submits("password", true);
+        data.set_submits("pin", true);
+        assert!(data.submits("password"));
+        assert!(data.submits("pin"));
+
+        data.set_submits("password", false);
+        data.set_submits("pin", false);
+        assert!(!data.submits("password"));
+        assert_eq!(data.meta("submit"), None);
+    }
+
+    #[test]
+    fn test_equality_ignores_insertion_order() {
+        let mut a = Secret::new();
+        a.insert("username".to_string(), "john".to_string());
+        a.insert("password".to_string(), "secret".to_string());
+
+        let mut b = Secret::new();
+        b.insert("password".to_string(), "secret".to_string());
+        b.insert("username".to_string(), "john".to_string());
+        assert_eq!(a, b);
+
+        b.insert("password".to_string(), "changed".to_string());
+        assert_ne!(a, b);
+    }
+
+    #[test]
+    fn test_equality_includes_metadata() {
+        let mut a = Secret::new();
+        a.insert("password".to_string(), "secret".to_string());
+        let mut b = a.clone();
+        assert_eq!(a, b);
+
+        b.set_submits("password", true);
+        assert_ne!(a, b);
+
+        a.set_submits("password", true);
+        assert_eq!(a, b);
+    }
+
+    #[test]
+    fn test_expires_at() {
+        let mut data = Secret::new();
+        assert_eq!(data.expires_at(), None);
+
+        data.set_expires_at(Some(1709209815));
+        assert_eq!(data.meta("expires_at"), Some("2024-02-29T12:30:15Z"));
+        assert_eq!(data.expires_at(), Some(1709209815));
+        assert!(data.is_empty());
+
+        data.set_meta("expires_at", "soon".to_string());
+        assert_eq!(data.expires_at(), None);
+
+        data.set_expires_at(None);
+        assert_eq!(data.meta("expires_at"), None);
+    }
+
+    #[test]
+    fn test_previous_values() {
+        let mut data = Secret::new();
+        data.insert("password".to_string(), "old".to_string());
+        data.set_previous("password", 1000);
+        data.insert("password".to_string(), "new".to_string());
+
+        assert_eq!(data.previous("password"), Some(("old", 1000)));
+        assert_eq!(data.previous_keys(), vec!["password"]);
+        assert_eq!(data.len(), 1);
+
+        assert!(!data.prune_previous(2000, 1000));
+        assert!(data.prune_previous(2001, 1000));
+        assert_eq!(data.previous("password"), None);
+        assert!(data.previous_keys().is_empty());
+    }
+
+    #[test]
+    fn test_prune_previous_drops_malformed() {
+        let mut data = Secret::new();
+        data.set_meta("previous_value:password", "old".to_string());
+        assert!(data.prune_previous(0, 1000));
+        assert_eq!(data.meta("previous_value:password"), None);
+    }
+
+    #[test]
+    fn test_previous_values_of_keys_starting_with_at() {
+        let mut data = Secret::new();
+        data.insert("x".to_string(), "old x".to_string());
+        data.insert("at_x".to_string(), "old at_x".to_string());
+        data.set_previous("x", 1000);
+        data.set_previous("at_x", 2000);
+
+        assert_eq!(data.previous("x"), Some(("old x", 1000)));
+        assert_eq!(data.previous("at_x"), Some(("old at_x", 2000)));
+        assert_eq!(data.previous_keys(), vec!["at_x", "x"]);
+    }
+
+    #[test]
+    fn test_upgrade_renames_legacy_previous_values() {
+        let v2 = parse(
+            r#"{"schema_version": 2, "fields": {"password": "new", "at_home": "b"},
+                "meta": {"previous_password": "old", "previous_at_password": "1000",
+                         "previous_at_home": "a", "previous_at_at_home": "2000",
+                         "previous_orphan": "x"}}"#,
+        );
+        let upgraded = v2.upgrade().unwrap();
+        assert_eq!(upgraded.previous("password"), Some(("old", 1000)));
+        assert_eq!(upgraded.previous("at_home"), Some(("a", 2000)));
+        assert_eq!(upgraded.previous_keys(), vec!["at_home", "password"]);
+        assert_eq!(upgraded.meta("previous_orphan"), None);
+    }
+
+    #[test]
+    fn test_sensitivity_override() {
+        let mut data = Secret::new();
+        data.insert("hint".to_string(), "first pet".to_string());
+        data.insert("security_token".to_string(), "abc".to_string());
+        assert!(!data.is_sensitive("hint"));
+        assert!(data.is_sensitive("security_token"));
+
+        data.set_sensitivity_override("hint", Some(true));
+        data.set_sensitivity_override("security_token", Some(false));
+        assert!(data.is_sensitive("hint"));
+        assert!(!data.is_sensitive("security_token"));
+        assert_eq!(data.meta["sensitive"], serde_json::json!(["hint"]));
+        assert_eq!(
+            data.meta["insensitive"],
+            serde_json::json!(["security_token"])
+        );
+
+        let hint = data.get("hint").unwrap();
+        assert_eq!(hint.to_string(), "hint: ********");
+        let kept = data.clone().filter(|f| !f.is_sensitive());
+        assert_eq!(kept.keys().collect::<Vec<_>>(), vec!["security_token"]);
+
+        data.set_sensitivity_override("hint", None);
+        assert_eq!(data.sensitivity_override("hint"), None);
+        assert!(!data.meta.contains_key("sensitive"));
+    }
+
+    #[test]
+    fn test_key_lists_allow_commas() {
+        let mut data = Secret::new();
+        data.insert("a,b".to_string(), "x".to_string());
+        data.set_submits("a,b", true);
+        data.set_sensitivity_override("a,b", Some(true));
+
+        let parsed = parse(&serde_json::to_string(&data).unwrap());
+        assert!(parsed.submits("a,b"));
+        assert!(!parsed.submits("a"));
+        assert_eq!(parsed.sensitivity_override("a,b"), Some(true));
+    }
+
+    #[test]
+    fn test_upgrade_splits_comma_separated_key_lists() {
+        let v2 = parse(
+            r#"{"schema_version": 2, "fields": {"a": "x", "b": "y"},
+                "meta": {"submit": "a,b", "insensitive": "b"}}"#,
+        );
+        assert!(!v2.submits("a"));
+        let upgraded = v2.upgrade().unwrap();
+        assert!(upgraded.submits("a"));
+        assert!(upgraded.submits("b"));
+        assert_eq!(upgraded.meta["submit"], serde_json::json!(["a", "b"]));
+        assert_eq!(upgraded.sensitivity_override("b"), Some(false));
+    }
+
+    #[test]
+    fn test_diff() {
+        let mut before = Secret::new();
+        before.insert("username".to_string(), "john".to_string());
+        before.insert("password".to_string(), "old".to_string());
+        before.insert("note".to_string(), "gone".to_string());
+
+        let mut after = before.clone();
+        after.insert("password".to_string(), "new".to_string());
+        after.insert("otp".to_string(), "JBSWY3DP".to_string());
+        after.remove("note");
+        after.set_submits("password", true);
+
+        let diff = after.diff(&before);
+        assert_eq!(diff.added, vec!["otp"]);
+        assert_eq!(diff.removed, vec!["note"]);
+        assert_eq!(diff.changed, vec!["password"]);
+        assert_eq!(
+            diff.to_string(),
+            "changed: password; added: otp; removed: note"
+        );
+        assert!(!diff.to_string().contains("new"));
+
+        assert!(before.diff(&before).is_empty());
+        assert_eq!(before.diff(&before).to_string(), "no changes");
+    }
+
+    #[test]
+    fn test_retain_keys() {
+        let mut data = Secret::new();
+        data.insert("username".to_string(), "john".to_string());
+        data.insert("password".to_string(), "secret".to_string());
+        data.insert("url".to_string(), "https://github.com".to_string());
+        data.set_submits("password", true);
+
+        data.retain_keys(&[
+            "username".to_string(),
+            "password".to_string(),
+            "url".to_string(),
+            "missing".to_string(),
+        ]);
+        assert_eq!(data.len(), 3);
+
+        data.set_previous("password", 1000);
+        data.set_previous("username", 1000);
+        data.retain_keys(&["username".to_string()]);
+        assert_eq!(data.keys().collect::<Vec<_>>(), vec!["username"]);
+        assert!(data.submits("password"));
+        assert_eq!(data.previous_keys(), vec!["username"]);
+    }
+
+    #[test]
+    fn test_into_attributes_excludes_sensitive_fields() {
+        let mut data = Secret::new();
+        data.insert("username".to_string(), "john".to_string());
+        data.insert("url".to_string(), "https://github.com".to_string());
+        data.insert("password".to_string(), "secret".to_string());
+        data.insert("label".to_string(), "other".to_string());
+        data.set_submits("password", true);
+
+        let keys: Vec<String> = ["username", "password", "label", "__submit"]
+            .map(String::from)
+            .to_vec();
+        let attributes = data.into_attributes(&keys);
+
+        assert_eq!(
+            attributes,
+            HashMap::from([("username".to_string(), "john".to_string())])
+        );
+    }
+
+    #[test]
+    fn test_merge() {
+        let mut a = Secret::new();
+        a.insert("username".to_string(), "john".to_string());
+        a.insert("password".to_string(), "secret".to_string());
+
+        let mut b = Secret::new();
+        b.insert("username".to_string(), "john".to_string());
+        b.insert("password".to_string(), "other".to_string());
+        b.insert("url".to_string(), "https://github.com".to_string());
+
+        let conflicts = a.merge(b);
+        assert_eq!(conflicts, vec!["password"]);
+        assert_eq!(a.get_value("password"), Some("secret"));
+        assert_eq!(a.get_value("url"), Some("https://github.com"));
+        assert_eq!(a.len(), 3);
+    }
+
+    #[test]
+    fn test_filter_sensitive() {
+        let mut data = Secret::new();
+        data.insert("username".to_string(), "john".to_string());
+        data.insert("password".to_string(), "secret".to_string());
+        data.insert("api_token".to_string(), "abc".to_string());
+
+        let filtered = data.filter(|f| !f.is_sensitive());
+        assert_eq!(filtered.len(), 1);
+        assert_eq!(filtered.get("username").unwrap().value, "john");
+    }
 }
@@ -1,84 +1,638 @@
-use crate::secret::Secret;
+use crate::display;
+use crate::field::Field;
+use crate::fuzzel;
+use crate::secret::{Secret, ICON_FIELD, RESERVED_ATTRIBUTES};
+use crate::settings;
 use anyhow::{Context, Result};
-use futures::future::try_join_all;
+use futures::future::{join_all, try_join_all};
 use oo7::{dbus::Service, AsAttributes};
 use std::collections::HashMap;
+use std::fmt;
+use std::future::Future;
+use tokio::sync::OnceCell;
 
-const FUZZEL_SCHEMA: &str = "org.fuzzel.secrets";
-const COLLECTION_LABEL: &str = "fuzzel-secrets";
+/// Schema of the items fuzzel-secrets stores, unless overridden with
+/// [`Settings::schema`](settings::Settings::schema)
+pub const FUZZEL_SCHEMA: &str = "org.fuzzel.secrets";
+/// Label of the collection holding the secrets, unless overridden with
+/// [`Settings::collection`](settings::Settings::collection)
+pub const COLLECTION_LABEL: &str = "fuzzel-secrets";
 
-/// Get all secrets from the fuzzel-secrets collection
+static SERVICE: OnceCell<Service<'static>> = OnceCell::const_new();
+
+/// The effective schema of the items that are stored and read
+pub fn schema() -> String {
+    settings::with(|settings| settings.schema.clone()).unwrap_or_else(|| FUZZEL_SCHEMA.to_string())
+}
+
+/// The effective label of the collection holding the secrets
+pub fn collection_label() -> String {
+    settings::with(|settings| settings.collection.clone())
+        .unwrap_or_else(|| COLLECTION_LABEL.to_string())
+}
+
+/// Error returned when a requested secret, field or collection doesn't exist
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NotFound {
+    Secret(String),
+    Field(String),
+    Collection,
+}
+
+impl fmt::Display for NotFound {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NotFound::Secret(label) => write!(f, "Secret not found: {}", label),
+            NotFound::Field(key) => write!(f, "Field not found: {}", key),
+            NotFound::Collection => write!(
+                f,
+                "Collection not found; it is created when storing the first secret"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for NotFound {}
+
+/// The kind of error an operation on a secret failed with, for callers telling
+/// failures apart without matching on messages
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SecretError {
+    /// The secret has no fields, which isn't stored or retrieved
+    Empty(String),
+    /// The secret, field or collection doesn't exist
+    NotFound(NotFound),
+    /// A secret with the label already exists, or several items share it
+    Duplicate(String),
+    /// The user dismissed a prompt
+    Cancelled,
+}
+
+impl SecretError {
+    /// The kind of an error, looking through the context added to it
+    ///
+    /// [`NotFound`] and [`fuzzel::Cancelled`], as returned by the lower level
+    /// helpers, are recognized as well.
+    pub fn of(err: &anyhow::Error) -> Option<SecretError> {
+        err.chain().find_map(|cause| {
+            if let Some(err) = cause.downcast_ref::<SecretError>() {
+                Some(err.clone())
+            } else if let Some(err) = cause.downcast_ref::<NotFound>() {
+                Some(SecretError::NotFound(err.clone()))
+            } else {
+                cause
+                    .is::<fuzzel::Cancelled>()
+                    .then_some(SecretError::Cancelled)
+            }
+        })
+    }
+}
+
+impl fmt::Display for SecretError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SecretError::Empty(label) => write!(f, "Secret has no fields: {}", label),
+            SecretError::NotFound(err) => err.fmt(f),
+            SecretError::Duplicate(label) => write!(f, "Secret already exists: {}", label),
+            SecretError::Cancelled => fuzzel::Cancelled.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for SecretError {}
+
+/// Identifies the item a secret was read from, so storing the edited secret
+/// replaces that item and no other sharing its label
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ItemPath(String);
+
+impl ItemPath {
+    fn of(item: &oo7::dbus::Item<'_>) -> Self {
+        ItemPath(item.path().to_string())
+    }
+}
+
+/// A secret as listed: its label and unencrypted item attributes
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SecretSummary {
+    pub label: String,
+    pub attributes: HashMap<String, String>,
+}
+
+impl SecretSummary {
+    /// Get the value of an attribute
+    pub fn attribute(&self, key: &str) -> Option<&str> {
+        self.attributes.get(key).map(String::as_str)
+    }
+
+    /// The icon named by the secret's `icon` field, if stored as an attribute
+    pub fn icon(&self) -> Option<&str> {
+        self.attribute(ICON_FIELD)
+    }
+
+    /// Fields stored as attributes, leaving out the ones identifying the item
+    pub fn fields(&self) -> Vec<Field> {
+        let mut fields: Vec<Field> = self
+            .attributes
+            .iter()
+            .filter(|(key, _)| !RESERVED_ATTRIBUTES.contains(&key.as_str()))
+            .map(|(key, value)| Field::new(key.clone(), value.clone()))
+            .collect();
+        fields.sort_by(|a, b| a.key.cmp(&b.key));
+        fields
+    }
+}
+
+/// Distinct values of an attribute across secrets, or without a key the
+/// distinct attribute keys besides the reserved ones, sorted
+pub fn tags(summaries: &[SecretSummary], key: Option<&str>) -> Vec<String> {
+    let mut tags: Vec<String> = summaries
+        .iter()
+        .flat_map(|summary| match key {
+            Some(key) => summary
+                .attribute(key)
+                .map(str::to_string)
+                .into_iter()
+                .collect(),
+            None => summary
+                .fields()
+                .into_iter()
+                .map(|f| f.key)
+                .collect::<Vec<_>>(),
+        })
+        .collect();
+    tags.sort();
+    tags.dedup();
+    tags
+}
+
+/// What could be read of every item, and why the other items couldn't be
+///
+/// One locked or broken item then doesn't hide all the others.
+#[derive(Debug)]
+pub struct Partial<T> {
+    pub read: Vec<T>,
+    pub failures: Vec<anyhow::Error>,
+}
+
+impl<T> Partial<T> {
+    /// Await every read, keeping the results and errors apart
+    async fn join(reads: impl IntoIterator<Item = impl Future<Output = Result<T>>>) -> Self {
+        let mut partial = Partial {
+            read: Vec::new(),
+            failures: Vec::new(),
+        };
+        for result in join_all(reads).await {
+            match result {
+                Ok(value) => partial.read.push(value),
+                Err(err) => partial.failures.push(err),
+            }
+        }
+        partial
+    }
+
+    /// The results, reporting the items that couldn't be read on stderr
+    ///
+    /// Fails with the first error only if no item could be read at all.
+    pub fn readable(self) -> Result<Vec<T>> {
+        if self.read.is_empty() && !self.failures.is_empty() {
+            return self.into_result();
+        }
+        for err in &self.failures {
+            eprintln!("Skipping unreadable item: {:#}", err);
+        }
+        Ok(self.read)
+    }
+
+    /// All results, or the first error if any item couldn't be read
+    pub fn into_result(self) -> Result<Vec<T>> {
+        match self.failures.into_iter().next() {
+            Some(err) => Err(err),
+            None => Ok(self.read),
+        }
+    }
+}
+
+/// List all secrets with their attributes, without decrypting them, as far as
+/// their items can be read
+pub async fn read_summaries() -> Result<Partial<SecretSummary>> {
+    let items = items().await?;
+    let summaries = items.iter().map(|item| async {
+        Ok(SecretSummary {
+            label: item.label().await.context("Failed to get item label")?,
+            attributes: item
+                .attributes()
+                .await
+                .context("Failed to get item attributes")?,
+        })
+    });
+    Ok(Partial::join(summaries).await)
+}
+
+/// List all secrets with their attributes, without decrypting them
+pub async fn list_summaries() -> Result<Vec<SecretSummary>> {
+    read_summaries().await?.into_result()
+}
+
+/// Get the labels of all secrets, as far as their items can be read
+pub async fn read_labels() -> Result<Partial<String>> {
+    let items = items().await?;
+    let labels = items
+        .iter()
+        .map(|item| async { item.label().await.context("Failed to get item label") });
+    Ok(Partial::join(labels).await)
+}
+
+/// Get the labels of all secrets from the fuzzel-secrets collection
+///
+/// Cheaper than [`list_summaries`] when the attributes aren't needed. Items
+/// that can't be read are skipped, see [`Partial::readable`], so one corrupt
+/// item doesn't make every picker unusable.
 pub async fn secrets() -> Result<Vec<String>> {
+    read_labels().await?.readable()
+}
+
+/// Result of decoding one stored item, see [`check_all`]
+pub struct CheckResult {
+    pub label: String,
+    pub result: Result<Secret>,
+}
+
+/// Decode every stored item without modifying anything
+///
+/// Unlike [`select_data`], items sharing a label are each decoded rather than
+/// picked from, so this never prompts except to unlock the collection.
+pub async fn check_all() -> Result<Vec<CheckResult>> {
     let items = items().await?;
-    let secrets_future = items.iter().map(|item| async {
-        let item_label = item.label().await.context("Failed to get item label")?;
-        Ok(item_label)
+    let lenient = settings::with(|settings| settings.lenient);
+    let checks = items.iter().map(|item| async move {
+        let label = item.label().await.context("Failed to get item label")?;
+        let result = match item.secret().await {
+            Ok(payload) => decode_payload(&label, &payload, lenient),
+            Err(err) => Err(err).with_context(|| format!("Failed to get secret '{}'", label)),
+        };
+        Ok(CheckResult { label, result })
     });
-    try_join_all(secrets_future).await
+    try_join_all(checks).await
 }
 
 /// Get all unique field keys from all existing secrets
+///
+/// Every item is read, including each of several sharing a label.
 pub async fn all_field_keys() -> Result<Vec<String>> {
     let mut keys = std::collections::HashSet::new();
 
-    for secret in secrets().await? {
-        let data = get_data(&secret).await?;
+    for item in items().await? {
+        let label = item.label().await.context("Failed to get item label")?;
+        let data = read_item(&label, &item).await?;
         keys.extend(data.keys().cloned());
     }
 
     Ok(keys.into_iter().collect())
 }
 
+/// The JSON payload a secret is stored as, in the current schema version
+pub fn encode_payload(data: &Secret) -> Result<String> {
+    let data = data.clone().upgrade()?;
+    serde_json::to_string(&data).context("Failed to serialize secret data")
+}
+
 /// Store a secret
-pub async fn store(label: &str, data: Secret) -> Result<()> {
-    let json_data = serde_json::to_string(&data).context("Failed to serialize secret data")?;
+///
+/// `replaces` is the item the secret was read from, if any, as returned by
+/// [`get_item`], [`select_item`] or [`item_path`]. Other items sharing the
+/// label are left alone.
+pub async fn store(label: &str, data: Secret, replaces: Option<&ItemPath>) -> Result<()> {
+    let data = data
+        .upgrade()
+        .with_context(|| format!("Failed to store secret '{}'", label))?;
+    let json_data = encode_payload(&data)?;
 
-    let attributes = HashMap::from([
+    let mut searchable_fields = settings::with(|settings| settings.searchable_fields.clone());
+    searchable_fields.push(ICON_FIELD.to_string());
+    let mut attributes = data.into_attributes(&searchable_fields);
+    attributes.extend([
         ("label".to_string(), label.to_string()), // Attributes are used by Secret Service for identification. Add label to ensure we store this as a separate item.
-        ("xdg:schema".to_string(), FUZZEL_SCHEMA.to_string()),
+        ("xdg:schema".to_string(), schema()),
     ]);
 
-    fuzzel_collection()
+    let item = fuzzel_collection()
         .await?
         .create_item(label, &attributes, json_data.as_bytes(), true, None)
         .await
-        .context("Failed to create item")?;
+        .with_context(|| format!("Failed to create item for '{}'", label))?;
+
+    // Items are only replaced when all attributes match, so the replaced item
+    // is left behind when its searchable fields changed
+    let Some(replaced) = replaces.filter(|path| **path != ItemPath::of(&item)) else {
+        return Ok(());
+    };
+    for previous in items_with_label(label).await? {
+        if ItemPath::of(&previous) == *replaced {
+            previous
+                .delete(None)
+                .await
+                .with_context(|| format!("Failed to delete previous item of '{}'", label))?;
+        }
+    }
     Ok(())
 }
 
+/// Get the Secret Service attributes of every item with the label
+///
+/// Attributes are stored unencrypted, so reading them never decrypts or
+/// prompts except to unlock the collection. Several items share a label when
+/// duplicates were stored, e.g. by other tools.
+pub async fn attributes(label: &str) -> Result<Vec<HashMap<String, String>>> {
+    let items = items_with_label(label).await?;
+    if items.is_empty() {
+        return Err(NotFound::Secret(label.to_string()).into());
+    }
+    try_join_all(items.iter().map(|item| item.attributes()))
+        .await
+        .with_context(|| format!("Failed to get attributes of '{}'", label))
+}
+
+/// Format attributes as "key: value" lines, sorted by key
+pub fn attribute_lines(attributes: &HashMap<String, String>) -> Vec<String> {
+    let mut lines: Vec<String> = attributes
+        .iter()
+        .map(|(key, value)| format!("{}: {}", key, value))
+        .collect();
+    lines.sort();
+    lines
+}
+
 /// Get the data for a specific secret
+///
+/// Never prompts: if several items share the label, this fails with
+/// [`SecretError::Duplicate`]. Use [`select_data`] where the user should pick.
 pub async fn get_data(label: &str) -> Result<Secret> {
+    get_item(label).await.map(|(data, _)| data)
+}
+
+/// Get the data for a specific secret like [`get_data`], along with the item
+/// it was read from
+pub async fn get_item(label: &str) -> Result<(Secret, ItemPath)> {
+    let item = only_item(label, items_with_label(label).await?)?;
+    Ok((read_item(label, &item).await?, ItemPath::of(&item)))
+}
+
+/// The item with the label, without reading its secret
+///
+/// Fails like [`get_data`] if several items share the label.
+pub async fn item_path(label: &str) -> Result<ItemPath> {
+    only_item(label, items_with_label(label).await?).map(|item| ItemPath::of(&item))
+}
+
+/// The single item with the label, failing if there is none or several
+fn only_item<T>(label: &str, items: Vec<T>) -> Result<T> {
+    let mut items = items.into_iter();
+    match (items.next(), items.next()) {
+        (None, _) => Err(NotFound::Secret(label.to_string()).into()),
+        (Some(item), None) => Ok(item),
+        (Some(_), Some(_)) => Err(
+            anyhow::Error::new(SecretError::Duplicate(label.to_string())).context(format!(
+                "Several items share the label '{}', pick one with `show`",
+                label
+            )),
+        ),
+    }
+}
+
+/// Get the data for a specific secret like [`get_data`], but if several items
+/// share the label, let the user pick one by the attributes they differ in
+pub async fn select_data(label: &str) -> Result<Secret> {
+    select_item(label).await.map(|(data, _)| data)
+}
+
+/// Get the data for a specific secret like [`select_data`], along with the
+/// item it was read from
+pub async fn select_item(label: &str) -> Result<(Secret, ItemPath)> {
     let items = items_with_label(label).await?;
-    match items.len() {
-        0 => Err(anyhow::anyhow!(format!("Secret not found: {}", label))),
-        1 => {
-            let secret_data = items
-                .first()
-                .unwrap()
-                .secret()
+    let item = match items.len() {
+        0 => return Err(NotFound::Secret(label.to_string()).into()),
+        1 => items.first().unwrap(),
+        _ => {
+            let attributes = try_join_all(items.iter().map(|item| item.attributes()))
                 .await
-                .context("Failed to get secret")?;
-            let json_str =
-                String::from_utf8(secret_data.to_vec()).context("Failed to decode secret data")?;
-            let data: Secret =
-                serde_json::from_str(&json_str).context("Failed to parse secret data as JSON")?;
-            return Ok(data);
+                .with_context(|| format!("Failed to get attributes of '{}' items", label))?;
+            let choices = describe_duplicates(label, &attributes);
+            let index = fuzzel::select_index(&choices, Some("Several items, pick one"))
+                .with_context(|| format!("Multiple secrets found with label: {}", label))?;
+            items
+                .get(index)
+                .ok_or_else(|| anyhow::anyhow!("Invalid index returned from fuzzel"))?
         }
-        _ => Err(anyhow::anyhow!(format!(
-            "Multiple secrets found with label: {}",
+    };
+    Ok((read_item(label, item).await?, ItemPath::of(item)))
+}
+
+/// Decrypt and decode the payload of an item
+async fn read_item(label: &str, item: &oo7::dbus::Item<'static>) -> Result<Secret> {
+    let secret_data = item
+        .secret()
+        .await
+        .with_context(|| format!("Failed to get secret '{}'", label))?;
+    decode_payload(
+        label,
+        &secret_data,
+        settings::with(|settings| settings.lenient),
+    )
+}
+
+/// Describe items sharing a label by the attributes whose values differ
+/// between them, falling back to numbering them
+fn describe_duplicates(label: &str, attributes: &[HashMap<String, String>]) -> Vec<String> {
+    let mut keys: Vec<&String> = attributes
+        .iter()
+        .flat_map(|attributes| attributes.keys())
+        .filter(|key| {
+            let first = attributes[0].get(*key);
+            attributes.iter().any(|other| other.get(*key) != first)
+        })
+        .collect();
+    keys.sort();
+    keys.dedup();
+
+    attributes
+        .iter()
+        .enumerate()
+        .map(|(index, item)| {
+            let distinguishing: Vec<String> = keys
+                .iter()
+                .filter_map(|key| item.get(*key).map(|value| format!("{}={}", key, value)))
+                .collect();
+            if distinguishing.is_empty() {
+                format!("{} (item {})", label, index + 1)
+            } else {
+                format!("{} ({})", label, distinguishing.join(", "))
+            }
+        })
+        .collect()
+}
+
+/// Decode the JSON payload of a fuzzel-secrets item
+fn decode_payload(label: &str, payload: &[u8], lenient: bool) -> Result<Secret> {
+    let json_str = if lenient {
+        String::from_utf8_lossy(payload)
+    } else {
+        std::str::from_utf8(payload)
+            .with_context(|| {
+                format!(
+                    "Secret '{}' is not valid UTF-8; it may not have been created by fuzzel-secrets (use --lenient to inspect it)",
+                    label
+                )
+            })?
+            .into()
+    };
+
+    let data: Secret = serde_json::from_str(&json_str).with_context(|| {
+        format!(
+            "Secret '{}' is not a JSON object of fields; it may not have been created by fuzzel-secrets",
             label
-        ))),
+        )
+    })?;
+    data.upgrade()
+        .with_context(|| format!("Failed to read secret '{}'", label))
+}
+
+/// Read all items of another schema from the default collection, e.g. ones
+/// created with `secret-tool`, converted to secrets keyed by item label
+pub async fn items_from_schema(schema: &str) -> Result<Vec<(String, Secret)>> {
+    let service = service().await?;
+    let collection = service
+        .default_collection()
+        .await
+        .context("Failed to get default collection")?;
+    let items = collection
+        .search_items(&HashMap::from([("xdg:schema", schema)]))
+        .await
+        .context("Failed to search items")?;
+
+    let secrets = items.iter().map(|item| async {
+        let label = item.label().await.context("Failed to get item label")?;
+        let attributes = item
+            .attributes()
+            .await
+            .context("Failed to get item attributes")?;
+        let payload = item.secret().await.context("Failed to get secret")?;
+        Ok((label, from_foreign_item(attributes, &payload)))
+    });
+    try_join_all(secrets).await
+}
+
+/// Map a foreign item to a secret
+///
+/// Attributes become fields. A JSON object payload is read as fields too, any
+/// other payload is stored as a single `value` field.
+fn from_foreign_item(attributes: HashMap<String, String>, payload: &[u8]) -> Secret {
+    let mut data: Secret = attributes
+        .into_iter()
+        .filter(|(key, _)| key != "xdg:schema")
+        .map(|(key, value)| Field::new(key, value))
+        .collect();
+
+    match serde_json::from_slice::<Secret>(payload) {
+        Ok(fields) => {
+            for field in fields.iter() {
+                data.insert(field.key, field.value);
+            }
+        }
+        Err(_) => data.insert(
+            "value".to_string(),
+            String::from_utf8_lossy(payload).into_owned(),
+        ),
     }
+
+    data
+}
+
+/// Delete a secret
+pub async fn delete(label: &str) -> Result<()> {
+    let items = items_with_label(label).await?;
+    if items.is_empty() {
+        return Err(NotFound::Secret(label.to_string()).into());
+    }
+
+    for item in items {
+        item.delete(None)
+            .await
+            .with_context(|| format!("Failed to delete '{}'", label))?;
+    }
+    Ok(())
+}
+
+/// Items of the collection, counted by whether they have the schema of
+/// fuzzel-secrets items
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CollectionItems {
+    pub secrets: usize,
+    /// Items stored by other applications, e.g. in a login keyring
+    pub foreign: usize,
+}
+
+/// Count every item of the collection, failing with [`NotFound`] if it
+/// doesn't exist rather than creating it
+pub async fn collection_items() -> Result<CollectionItems> {
+    let collection = existing_collection().await?.ok_or(NotFound::Collection)?;
+    count_items(&collection).await
+}
+
+async fn count_items(collection: &oo7::dbus::Collection<'static>) -> Result<CollectionItems> {
+    let schema = schema();
+    let mut count = CollectionItems::default();
+    for item in collection
+        .items()
+        .await
+        .context("Failed to list collection items")?
+    {
+        let attributes = item
+            .attributes()
+            .await
+            .context("Failed to get item attributes")?;
+        if attributes.get("xdg:schema") == Some(&schema) {
+            count.secrets += 1;
+        } else {
+            count.foreign += 1;
+        }
+    }
+    Ok(count)
+}
+
+/// Delete the whole fuzzel-secrets collection, including every secret in it
+///
+/// Collections that also hold items of other applications are never deleted,
+/// since `--collection` can name any collection. Returns the number of
+/// secrets deleted.
+pub async fn delete_collection() -> Result<usize> {
+    let collection = existing_collection().await?.ok_or(NotFound::Collection)?;
+    let count = count_items(&collection).await?;
+    if count.foreign > 0 {
+        anyhow::bail!(
+            "Collection '{}' holds {} items not stored by fuzzel-secrets; refusing to delete it",
+            collection_label(),
+            count.foreign
+        );
+    }
+
+    collection
+        .delete(None)
+        .await
+        .context("Failed to delete collection")?;
+    Ok(count.secrets)
 }
 
 async fn items_with_label(label: &str) -> Result<Vec<oo7::dbus::Item<'static>>> {
-    let mut attributes = HashMap::from([("xdg:schema", FUZZEL_SCHEMA)]);
+    let schema = schema();
+    let mut attributes = HashMap::from([("xdg:schema", schema.as_str())]);
     attributes.insert("label", label);
     search_items_by_attributes(attributes).await
 }
 
 async fn items() -> Result<Vec<oo7::dbus::Item<'static>>> {
-    search_items_by_attributes(HashMap::from([("xdg:schema", FUZZEL_SCHEMA)])).await
+    let schema = schema();
+    search_items_by_attributes(HashMap::from([("xdg:schema", schema.as_str())])).await
 }
 
 async fn search_items_by_attributes(
@@ -91,25 +645,351 @@ async fn search_items_by_attributes(
         .context("Failed to search items")
 }
 
+/// A Secret Service collection, which need not belong to fuzzel-secrets
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CollectionInfo {
+    pub label: String,
+    pub locked: bool,
+}
+
+/// List all collections known to the Secret Service
+pub async fn collections() -> Result<Vec<CollectionInfo>> {
+    let service = service().await?;
+    let collections = service
+        .collections()
+        .await
+        .context("Failed to list collections")?;
+
+    let infos = collections.iter().map(|collection| async {
+        Ok(CollectionInfo {
+            label: collection
+                .label()
+                .await
+                .context("Failed to get collection label")?,
+            locked: collection
+                .is_locked()
+                .await
+                .context("Failed to get collection lock state")?,
+        })
+    });
+    try_join_all(infos).await
+}
+
+/// Result of explicitly unlocking the collection
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnlockStatus {
+    Unlocked,
+    AlreadyUnlocked,
+    NotFound,
+}
+
+/// Unlock the fuzzel-secrets collection without creating it
+pub async fn unlock() -> Result<UnlockStatus> {
+    let service = service().await?;
+    let Some(collection) = service.with_label(&collection_label()).await? else {
+        return Ok(UnlockStatus::NotFound);
+    };
+
+    if !collection.is_locked().await? {
+        return Ok(UnlockStatus::AlreadyUnlocked);
+    }
+    display::require()?;
+
+    collection
+        .unlock(None)
+        .await
+        .context("Failed to unlock collection")?;
+    Ok(UnlockStatus::Unlocked)
+}
+
+/// Connection to the Secret Service, established once and shared by all calls
+async fn service() -> Result<&'static Service<'static>> {
+    SERVICE
+        .get_or_try_init(|| async {
+            Service::new()
+                .await
+                .context("Failed to connect to Secret Service")
+        })
+        .await
+}
+
 async fn fuzzel_collection() -> Result<oo7::dbus::Collection<'static>> {
-    let service = Service::new()
+    if let Some(collection) = existing_collection().await? {
+        return Ok(collection);
+    }
+    let label = collection_label();
+    service()
+        .await?
+        .create_collection(&label, "", None)
         .await
-        .context("Failed to connect to Secret Service")?;
-    let collection = match service.with_label(COLLECTION_LABEL).await? {
-        Some(col) => col,
-        None => service
-            .create_collection(COLLECTION_LABEL, "", None)
-            .await
-            .context("Failed to create fuzzel-secrets collection")?,
+        .with_context(|| format!("Failed to create collection '{}'", label))
+}
+
+/// The collection holding the secrets, unlocked, or `None` if it doesn't exist
+async fn existing_collection() -> Result<Option<oo7::dbus::Collection<'static>>> {
+    let service = service().await?;
+    let label = collection_label();
+    let Some(collection) = service.with_label(&label).await? else {
+        return Ok(None);
     };
 
     // Unlock the collection if it's locked
     if collection.is_locked().await? {
+        if !settings::with(|settings| settings.auto_unlock) {
+            anyhow::bail!(
+                "Collection '{}' is locked and auto-unlock is disabled; run `fuzzel-secrets unlock` first",
+                label
+            );
+        }
+        // The unlock prompt can't render headless, and would hang instead
+        display::require()?;
         collection
             .unlock(None)
             .await
             .context("Failed to unlock collection")?;
     }
 
-    Ok(collection)
+    Ok(Some(collection))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_schema_override() {
+        let _guard = settings::test_guard();
+        assert_eq!(schema(), FUZZEL_SCHEMA);
+        settings::update(|settings| settings.schema = Some("org.example.Password".to_string()));
+        assert_eq!(schema(), "org.example.Password");
+        settings::update(|settings| settings.schema = None);
+        assert_eq!(schema(), FUZZEL_SCHEMA);
+    }
+
+    #[test]
+    fn test_collection_override() {
+        let _guard = settings::test_guard();
+        assert_eq!(collection_label(), COLLECTION_LABEL);
+        settings::update(|settings| settings.collection = Some("work".to_string()));
+        assert_eq!(collection_label(), "work");
+        settings::update(|settings| settings.collection = None);
+        assert_eq!(collection_label(), COLLECTION_LABEL);
+    }
+
+    #[tokio::test]
+    async fn test_partial_keeps_readable_items() {
+        let reads = ["GitHub", "", "Gmail"].map(|label| async move {
+            if label.is_empty() {
+                anyhow::bail!("Failed to get item label")
+            }
+            Ok(label.to_string())
+        });
+        let partial = Partial::join(reads).await;
+        assert_eq!(partial.read, vec!["GitHub", "Gmail"]);
+        assert_eq!(partial.failures.len(), 1);
+        assert!(partial.into_result().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_readable_skips_failing_item() {
+        let read = |label: &'static str| async move {
+            if label.is_empty() {
+                anyhow::bail!("Failed to get item label")
+            }
+            Ok(label.to_string())
+        };
+
+        let partial = Partial::join(["GitHub", "", "Gmail"].map(read)).await;
+        assert_eq!(partial.readable().unwrap(), vec!["GitHub", "Gmail"]);
+
+        let partial = Partial::join(["GitHub"].map(read)).await;
+        assert_eq!(partial.readable().unwrap(), vec!["GitHub"]);
+
+        let partial = Partial::join(["", ""].map(read)).await;
+        assert!(partial.readable().is_err());
+
+        let partial = Partial::join(Vec::<std::future::Ready<Result<String>>>::new()).await;
+        assert!(partial.readable().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_attribute_lines() {
+        let attributes = HashMap::from([
+            ("xdg:schema".to_string(), FUZZEL_SCHEMA.to_string()),
+            ("label".to_string(), "GitHub".to_string()),
+            ("username".to_string(), "john".to_string()),
+        ]);
+        assert_eq!(
+            attribute_lines(&attributes),
+            vec![
+                "label: GitHub",
+                "username: john",
+                "xdg:schema: org.fuzzel.secrets"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tags() {
+        let summary = |label: &str, attributes: &[(&str, &str)]| SecretSummary {
+            label: label.to_string(),
+            attributes: attributes
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .chain([("label".to_string(), label.to_string())])
+                .collect(),
+        };
+        let summaries = [
+            summary("github", &[("category", "work"), ("username", "john")]),
+            summary("gmail", &[("category", "personal")]),
+            summary("aws", &[("category", "work")]),
+            summary("bank", &[]),
+        ];
+
+        assert_eq!(tags(&summaries, Some("category")), vec!["personal", "work"]);
+        assert_eq!(tags(&summaries, None), vec!["category", "username"]);
+        assert!(tags(&summaries, Some("missing")).is_empty());
+    }
+
+    #[test]
+    fn test_summary_fields_skip_reserved_attributes() {
+        let summary = SecretSummary {
+            label: "github".to_string(),
+            attributes: HashMap::from([
+                ("label".to_string(), "github".to_string()),
+                ("xdg:schema".to_string(), FUZZEL_SCHEMA.to_string()),
+                ("username".to_string(), "john".to_string()),
+                ("icon".to_string(), "web-browser".to_string()),
+            ]),
+        };
+
+        assert_eq!(summary.icon(), Some("web-browser"));
+        assert_eq!(
+            summary.fields(),
+            vec![
+                Field::new("icon".to_string(), "web-browser".to_string()),
+                Field::new("username".to_string(), "john".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_secret_error_of() {
+        let err = anyhow::Error::new(SecretError::Duplicate("github".to_string()))
+            .context("Failed to duplicate 'gitlab'");
+        assert_eq!(
+            SecretError::of(&err),
+            Some(SecretError::Duplicate("github".to_string()))
+        );
+        assert_eq!(
+            err.root_cause().to_string(),
+            "Secret already exists: github"
+        );
+
+        let err = anyhow::Error::new(NotFound::Collection).context("Failed to list secrets");
+        assert_eq!(
+            SecretError::of(&err),
+            Some(SecretError::NotFound(NotFound::Collection))
+        );
+        let err = anyhow::Error::new(fuzzel::Cancelled).context("Failed to select secret");
+        assert_eq!(SecretError::of(&err), Some(SecretError::Cancelled));
+        assert_eq!(SecretError::of(&anyhow::anyhow!("No label provided")), None);
+    }
+
+    #[test]
+    fn test_not_found_names_label_and_field() {
+        let err: anyhow::Error = NotFound::Secret("github".to_string()).into();
+        assert_eq!(err.to_string(), "Secret not found: github");
+        let err: anyhow::Error = NotFound::Field("password".to_string()).into();
+        assert_eq!(err.to_string(), "Field not found: password");
+    }
+
+    #[test]
+    fn test_only_item_refuses_duplicates() {
+        assert_eq!(only_item("github", vec![1]).unwrap(), 1);
+
+        let err = only_item::<u32>("github", Vec::new()).unwrap_err();
+        assert_eq!(
+            SecretError::of(&err),
+            Some(SecretError::NotFound(NotFound::Secret(
+                "github".to_string()
+            )))
+        );
+
+        let err = only_item("github", vec![1, 2]).unwrap_err();
+        assert_eq!(
+            SecretError::of(&err),
+            Some(SecretError::Duplicate("github".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_describe_duplicates() {
+        let attributes = |account: &str| {
+            HashMap::from([
+                ("label".to_string(), "github".to_string()),
+                ("account".to_string(), account.to_string()),
+            ])
+        };
+
+        assert_eq!(
+            describe_duplicates("github", &[attributes("work"), attributes("home")]),
+            vec!["github (account=work)", "github (account=home)"]
+        );
+        assert_eq!(
+            describe_duplicates("github", &[attributes("work"), attributes("work")]),
+            vec!["github (item 1)", "github (item 2)"]
+        );
+    }
+
+    #[test]
+    fn test_decode_payload_names_label() {
+        let err = decode_payload("github", b"\xff\xfe", false).unwrap_err();
+        assert!(err.to_string().contains("'github'"));
+
+        let err = decode_payload("github", b"plain text", false).unwrap_err();
+        assert!(err.to_string().contains("'github'"));
+    }
+
+    #[test]
+    fn test_encode_payload_round_trip() {
+        let mut data = Secret::new();
+        data.insert("password".to_string(), "hunter2".to_string());
+        let payload = encode_payload(&data).unwrap();
+        assert!(payload.contains(&format!(
+            "\"schema_version\":{}",
+            crate::secret::SCHEMA_VERSION
+        )));
+        assert_eq!(
+            decode_payload("github", payload.as_bytes(), false).unwrap(),
+            data
+        );
+    }
+
+    #[test]
+    fn test_decode_payload_lenient() {
+        let data = decode_payload("github", b"{\"note\": \"a\xffb\"}", true).unwrap();
+        assert_eq!(data.get_value("note"), Some("a\u{fffd}b"));
+        assert!(decode_payload("github", b"{\"note\": \"a\xffb\"}", false).is_err());
+    }
+
+    #[test]
+    fn test_from_foreign_item_plain_payload() {
+        let attributes = HashMap::from([
+            ("xdg:schema".to_string(), "org.example.Password".to_string()),
+            ("username".to_string(), "john".to_string()),
+        ]);
+
+        let data = from_foreign_item(attributes, b"hunter2");
+        assert_eq!(data.len(), 2);
+        assert_eq!(data.get_value("username"), Some("john"));
+        assert_eq!(data.get_value("value"), Some("hunter2"));
+    }
+
+    #[test]
+    fn test_from_foreign_item_json_payload() {
+        let data = from_foreign_item(HashMap::new(), br#"{"password": "secret"}"#);
+        assert_eq!(data.len(), 1);
+        assert_eq!(data.get_value("password"), Some("secret"));
+    }
 }
@@ -0,0 +1,66 @@
+use crate::xdg;
+use anyhow::{bail, Context, Result};
+use std::fs::{self, Permissions};
+use std::io::ErrorKind;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::net::{UnixListener, UnixStream};
+
+/// How long a client may take to send its request after connecting
+pub const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Location of the socket `serve` listens on
+pub fn socket_path() -> PathBuf {
+    xdg::runtime_dir().join("fuzzel-secrets.sock")
+}
+
+/// Listen on a socket only the current user can connect to
+///
+/// A socket left behind by a server that is no longer running is replaced, but
+/// a running server is never taken over.
+pub async fn bind(path: &Path) -> Result<UnixListener> {
+    match fs::symlink_metadata(path) {
+        Ok(_) => {
+            if UnixStream::connect(path).await.is_ok() {
+                bail!("Already serving on {}", path.display());
+            }
+            fs::remove_file(path)
+                .with_context(|| format!("Failed to remove stale socket {}", path.display()))?;
+        }
+        Err(err) if err.kind() == ErrorKind::NotFound => {}
+        Err(err) => {
+            return Err(err).with_context(|| format!("Failed to inspect {}", path.display()))
+        }
+    }
+
+    let listener =
+        UnixListener::bind(path).with_context(|| format!("Failed to bind {}", path.display()))?;
+    // $XDG_RUNTIME_DIR is private already, this also covers the fallback
+    fs::set_permissions(path, Permissions::from_mode(0o600))
+        .with_context(|| format!("Failed to restrict permissions of {}", path.display()))?;
+
+    Ok(listener)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_bind_is_private_and_replaces_stale_socket() {
+        let path =
+            std::env::temp_dir().join(format!("fuzzel-secrets-serve-{}.sock", std::process::id()));
+        let _ = fs::remove_file(&path);
+
+        let listener = bind(&path).await.unwrap();
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        assert!(bind(&path).await.is_err());
+
+        // Dropping the listener leaves the socket file behind, like a crash
+        drop(listener);
+        bind(&path).await.unwrap();
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,138 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::RwLock;
+
+/// Options of an invocation, built once from the configuration and command line
+///
+/// Helpers deep in the library read the current settings instead of taking
+/// every option as an argument. Until [`set`] is called they see
+/// [`Settings::default`], so the helpers also work standalone.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Settings {
+    /// Unlock a locked collection automatically instead of failing
+    pub auto_unlock: bool,
+    /// Decode payloads that aren't valid UTF-8 lossily instead of failing,
+    /// which helps diagnosing items written by other tools
+    pub lenient: bool,
+    /// Fields stored as searchable item attributes besides the encrypted
+    /// payload; sensitive fields never are
+    pub searchable_fields: Vec<String>,
+    /// Key patterns whose values are partially masked, matching keys that
+    /// contain them, ignoring case
+    pub partial_mask_fields: Vec<String>,
+    /// Schema of the items that are stored and read, `None` for
+    /// [`FUZZEL_SCHEMA`](crate::secrets::FUZZEL_SCHEMA)
+    pub schema: Option<String>,
+    /// Label of the collection holding the secrets, `None` for
+    /// [`COLLECTION_LABEL`](crate::secrets::COLLECTION_LABEL)
+    pub collection: Option<String>,
+    /// Program run instead of `fuzzel`, see
+    /// [`FUZZEL_BIN_VAR`](crate::fuzzel::FUZZEL_BIN_VAR)
+    pub fuzzel_bin: Option<PathBuf>,
+    /// fuzzel configuration file passed with `--config` to every invocation
+    pub fuzzel_config: Option<PathBuf>,
+    /// Let fuzzel record selections in its usage cache
+    ///
+    /// Disabled by default: the cache would persist which secret labels were
+    /// picked, outside of the Secret Service, and reorder entries by usage.
+    pub fuzzel_cache: bool,
+    /// Width of fuzzel windows in characters, instead of fuzzel's own default
+    pub fuzzel_width: Option<u32>,
+    /// Allow prompts; when unset they fail with
+    /// [`NotInteractive`](crate::fuzzel::NotInteractive) instead
+    pub interactive: bool,
+}
+
+impl Settings {
+    const DEFAULT: Settings = Settings {
+        auto_unlock: true,
+        lenient: false,
+        searchable_fields: Vec::new(),
+        partial_mask_fields: Vec::new(),
+        schema: None,
+        collection: None,
+        fuzzel_bin: None,
+        fuzzel_config: None,
+        fuzzel_cache: false,
+        fuzzel_width: None,
+        interactive: true,
+    };
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// The collection label a name given on the command line stands for
+///
+/// Names without an alias are taken literally, as labels.
+pub fn resolve_collection(name: String, aliases: &HashMap<String, String>) -> String {
+    match aliases.get(&name) {
+        Some(label) => label.clone(),
+        None => name,
+    }
+}
+
+static CURRENT: RwLock<Settings> = RwLock::new(Settings::DEFAULT);
+
+/// Replace the settings of this process
+///
+/// Set them once before accessing any secrets, so every operation of an
+/// invocation uses the same schema and collection.
+pub fn set(settings: Settings) {
+    *CURRENT.write().unwrap() = settings;
+}
+
+/// Change some of the settings of this process
+pub fn update(change: impl FnOnce(&mut Settings)) {
+    change(&mut CURRENT.write().unwrap());
+}
+
+/// Read the current settings
+pub fn with<T>(read: impl FnOnce(&Settings) -> T) -> T {
+    read(&CURRENT.read().unwrap())
+}
+
+/// Held by tests changing the settings, or environment variables helpers
+/// read, so tests running in parallel don't see each other's changes
+#[cfg(test)]
+pub(crate) fn test_guard() -> std::sync::MutexGuard<'static, ()> {
+    static GUARD: std::sync::Mutex<()> = std::sync::Mutex::new(());
+    GUARD
+        .lock()
+        .unwrap_or_else(std::sync::PoisonError::into_inner)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_update_keeps_other_settings() {
+        let _guard = test_guard();
+        let before = with(Settings::clone);
+        update(|settings| settings.lenient = !settings.lenient);
+        assert_eq!(with(|settings| settings.lenient), !before.lenient);
+        assert_eq!(
+            with(|settings| settings.collection.clone()),
+            before.collection
+        );
+        update(|settings| settings.lenient = before.lenient);
+    }
+
+    #[test]
+    fn test_resolve_collection() {
+        let aliases = HashMap::from([("work".to_string(), "Work secrets (ACME)".to_string())]);
+        assert_eq!(
+            resolve_collection("work".to_string(), &aliases),
+            "Work secrets (ACME)"
+        );
+        assert_eq!(resolve_collection("Work".to_string(), &aliases), "Work");
+        assert_eq!(
+            resolve_collection("home".to_string(), &HashMap::new()),
+            "home"
+        );
+    }
+}
@@ -0,0 +1,47 @@
+use anyhow::{bail, Context, Result};
+use std::process::{Command, Stdio};
+
+/// Run a shell command and return its stdout without the trailing newline
+///
+/// Only a single trailing newline is removed, so values that intentionally end
+/// in whitespace survive. Fails if the command exits unsuccessfully.
+pub fn output(command: &str) -> Result<String> {
+    let output = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .stdin(Stdio::null())
+        .stderr(Stdio::inherit())
+        .output()
+        .context("Failed to execute command")?;
+
+    if !output.status.success() {
+        bail!("Command failed with {}", output.status);
+    }
+
+    let mut result = String::from_utf8(output.stdout).context("Command output is not UTF-8")?;
+    if result.ends_with('\n') {
+        result.pop();
+        if result.ends_with('\r') {
+            result.pop();
+        }
+    }
+
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_output_strips_single_newline() {
+        assert_eq!(output("printf 'abc\\n'").unwrap(), "abc");
+        assert_eq!(output("printf 'abc \\n\\n'").unwrap(), "abc \n");
+        assert_eq!(output("printf 'abc'").unwrap(), "abc");
+    }
+
+    #[test]
+    fn test_output_fails_on_error_status() {
+        assert!(output("echo value; exit 3").is_err());
+    }
+}
@@ -0,0 +1,276 @@
+use anyhow::{bail, Context, Result};
+use clap::ValueEnum;
+use serde::Deserialize;
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+/// The text typed for a stored value
+///
+/// Values are typed verbatim, including tabs, other control characters and
+/// leading dashes, except that a single trailing newline (`\n` or `\r\n`) is
+/// dropped. Imported values often carry one by accident, and typing it would
+/// press Enter, which `--enter` and auto-submit exist for.
+pub fn text_to_type(value: &str) -> &str {
+    value
+        .strip_suffix('\n')
+        .map(|value| value.strip_suffix('\r').unwrap_or(value))
+        .unwrap_or(value)
+}
+
+/// How wtype types values
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum TypeMode {
+    /// As text, which is fast but may mistype characters on some layouts
+    #[default]
+    Text,
+    /// One Unicode keysym per character, which is slower but layout-independent
+    Keysym,
+}
+
+/// Placeholder replaced by the value in a custom type command
+pub const VALUE_PLACEHOLDER: &str = "{value}";
+/// Argument of a custom type command marking that it reads the value from stdin
+pub const STDIN_MARKER: &str = "{stdin}";
+
+/// Type a value into the focused window with wtype
+pub fn type_text(value: &str) -> Result<()> {
+    wtype_text("wtype", &[], value)
+}
+
+/// Type a value like [`type_text`], running wtype as `program` with the
+/// leading `args`
+fn wtype_text(program: &str, args: &[&str], value: &str) -> Result<()> {
+    let args: Vec<String> = args
+        .iter()
+        .chain(&["-"])
+        .map(|arg| arg.to_string())
+        .collect();
+    run(program, &args, Some(text_to_type(value)))
+}
+
+/// Type a value into the focused window with wtype, pressing a keysym per character
+///
+/// Unlike [`type_text`], the keysyms are passed as arguments, which other
+/// processes can see while wtype runs.
+pub fn type_keysyms(value: &str) -> Result<()> {
+    run("wtype", &keysym_args(text_to_type(value))?, None)
+}
+
+/// wtype arguments pressing the keysym of every character of the text
+///
+/// Keysyms are named after the Unicode codepoint, such as `U20AC` for `€`,
+/// which xkbcommon resolves regardless of the keyboard layout. Tabs and
+/// newlines press Tab and Return; other control characters can't be typed.
+fn keysym_args(text: &str) -> Result<Vec<String>> {
+    let mut args = Vec::new();
+    for c in text.chars() {
+        let keysym = match c {
+            '\t' => "Tab".to_string(),
+            '\n' => "Return".to_string(),
+            c if c.is_control() => bail!("U+{:04X} can't be typed as a keysym", c as u32),
+            c => format!("U{:04X}", c as u32),
+        };
+        args.push("-k".to_string());
+        args.push(keysym);
+    }
+    Ok(args)
+}
+
+/// Type a value with a custom command such as `["ydotool", "type", "{value}"]`
+///
+/// The command must contain exactly one `{value}` placeholder, replaced by the
+/// value, or a `{stdin}` argument, which is dropped and the value written to
+/// the command's stdin instead. Prefer `{stdin}` where the tool supports it, as
+/// arguments are visible to other processes.
+pub fn type_with_command(template: &[String], value: &str) -> Result<()> {
+    let (program, args, stdin) = build_command(template, text_to_type(value))?;
+    run(program, &args, stdin)
+}
+
+/// Split a custom type command into program, arguments and stdin
+///
+/// The placeholder is substituted once, so a value containing `{value}` itself
+/// is passed on verbatim.
+fn build_command<'a>(
+    template: &'a [String],
+    value: &'a str,
+) -> Result<(&'a str, Vec<String>, Option<&'a str>)> {
+    let Some((program, args)) = template.split_first() else {
+        bail!("type_command is empty");
+    };
+    if program.contains(VALUE_PLACEHOLDER) || program == STDIN_MARKER {
+        bail!("type_command must start with a program, not a placeholder");
+    }
+
+    let placeholders: usize = args
+        .iter()
+        .map(|arg| arg.matches(VALUE_PLACEHOLDER).count())
+        .sum();
+    let markers = args.iter().filter(|arg| *arg == STDIN_MARKER).count();
+    match (placeholders, markers) {
+        (1, 0) => Ok((
+            program,
+            args.iter()
+                .map(|arg| arg.replacen(VALUE_PLACEHOLDER, value, 1))
+                .collect(),
+            None,
+        )),
+        (0, 1) => Ok((
+            program,
+            args.iter()
+                .filter(|arg| *arg != STDIN_MARKER)
+                .cloned()
+                .collect(),
+            Some(value),
+        )),
+        _ => bail!(
+            "type_command must contain exactly one {} placeholder or {} argument",
+            VALUE_PLACEHOLDER,
+            STDIN_MARKER
+        ),
+    }
+}
+
+/// Run a typing program, feeding it `stdin` if given
+fn run(program: &str, args: &[String], stdin: Option<&str>) -> Result<()> {
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(if stdin.is_some() {
+            Stdio::piped()
+        } else {
+            Stdio::null()
+        })
+        .spawn()
+        .with_context(|| format!("Failed to execute {}", program))?;
+
+    if let (Some(mut pipe), Some(text)) = (child.stdin.take(), stdin) {
+        pipe.write_all(text.as_bytes())
+            .with_context(|| format!("Failed to write to {} stdin", program))?;
+    }
+
+    let status = child
+        .wait()
+        .with_context(|| format!("Failed to wait for {}", program))?;
+    if !status.success() {
+        bail!("{} command failed", program);
+    }
+
+    Ok(())
+}
+
+/// Press Enter in the focused window, e.g. to submit a form
+pub fn press_enter() -> Result<()> {
+    run("wtype", &["-k".to_string(), "Return".to_string()], None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::path::PathBuf;
+
+    /// Create a stub wtype recording its arguments and stdin in a fresh directory
+    ///
+    /// The stub is run through `sh` rather than executed directly, which avoids
+    /// ETXTBSY races with other tests spawning processes.
+    fn stub(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("fuzzel-secrets-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let script = dir.join("wtype");
+        fs::write(
+            &script,
+            format!(
+                "printf '%s\\n' \"$@\" > '{0}/args'\ncat > '{0}/stdin'\n",
+                dir.display()
+            ),
+        )
+        .unwrap();
+        dir
+    }
+
+    fn typed(name: &str, value: &str) -> (String, Vec<u8>) {
+        let dir = stub(name);
+        let template = [
+            "sh".to_string(),
+            dir.join("wtype").to_str().unwrap().to_string(),
+            "-".to_string(),
+            STDIN_MARKER.to_string(),
+        ];
+        type_with_command(&template, value).unwrap();
+        let args = fs::read_to_string(dir.join("args")).unwrap();
+        let stdin = fs::read(dir.join("stdin")).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        (args, stdin)
+    }
+
+    #[test]
+    fn test_type_text_pipes_value_to_wtype() {
+        let dir = stub("type-text");
+        let script = dir.join("wtype");
+        wtype_text("sh", &[script.to_str().unwrap()], "--pa ss\n").unwrap();
+        assert_eq!(fs::read_to_string(dir.join("args")).unwrap(), "-\n");
+        assert_eq!(fs::read(dir.join("stdin")).unwrap(), b"--pa ss");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_value_is_passed_on_stdin() {
+        let (args, stdin) = typed("stdin", "--not-a-flag");
+        assert_eq!(args, "-\n");
+        assert_eq!(stdin, b"--not-a-flag");
+    }
+
+    #[test]
+    fn test_single_trailing_newline_is_stripped() {
+        assert_eq!(typed("newline", "secret\n").1, b"secret");
+        assert_eq!(typed("crlf", "secret\r\n").1, b"secret");
+        assert_eq!(typed("newlines", "secret\n\n").1, b"secret\n");
+    }
+
+    #[test]
+    fn test_value_placeholder_is_substituted_once() {
+        let template: Vec<String> = ["ydotool", "type", "--text={value}"]
+            .map(str::to_string)
+            .to_vec();
+        let (program, args, stdin) = build_command(&template, "{value}{value}").unwrap();
+        assert_eq!(program, "ydotool");
+        assert_eq!(args, vec!["type", "--text={value}{value}"]);
+        assert_eq!(stdin, None);
+    }
+
+    #[test]
+    fn test_keysym_args() {
+        assert_eq!(
+            keysym_args("a€\t").unwrap(),
+            vec!["-k", "U0061", "-k", "U20AC", "-k", "Tab"]
+        );
+        assert_eq!(keysym_args("😀").unwrap(), vec!["-k", "U1F600"]);
+        assert!(keysym_args("").unwrap().is_empty());
+        assert!(keysym_args("a\x07").is_err());
+    }
+
+    #[test]
+    fn test_invalid_templates_are_rejected() {
+        for template in [
+            vec![],
+            vec!["{value}"],
+            vec!["wtype"],
+            vec!["wtype", "{value}", "{value}"],
+            vec!["wtype", "{value}", "{stdin}"],
+            vec!["wtype", "{stdin}", "{stdin}"],
+        ] {
+            let template: Vec<String> = template.into_iter().map(str::to_string).collect();
+            assert!(build_command(&template, "x").is_err(), "{:?}", template);
+        }
+    }
+
+    #[test]
+    fn test_embedded_control_characters_are_kept() {
+        assert_eq!(
+            typed("control", "user\tpa ss\nword ").1,
+            b"user\tpa ss\nword "
+        );
+    }
+}
@@ -0,0 +1,29 @@
+use std::env;
+use std::path::PathBuf;
+
+/// Resolve an XDG base directory, falling back to a path below `$HOME`
+fn base_dir(var: &str, home_fallback: &str) -> Option<PathBuf> {
+    env::var_os(var)
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(home_fallback)))
+}
+
+/// Directory holding the fuzzel-secrets configuration
+pub fn config_dir() -> Option<PathBuf> {
+    base_dir("XDG_CONFIG_HOME", ".config").map(|dir| dir.join("fuzzel-secrets"))
+}
+
+/// Directory for runtime files such as locks, falling back to the temporary
+/// directory when `$XDG_RUNTIME_DIR` isn't set
+pub fn runtime_dir() -> PathBuf {
+    env::var_os("XDG_RUNTIME_DIR")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .unwrap_or_else(env::temp_dir)
+}
+
+/// Directory holding fuzzel-secrets state such as history
+pub fn state_dir() -> Option<PathBuf> {
+    base_dir("XDG_STATE_HOME", ".local/state").map(|dir| dir.join("fuzzel-secrets"))
+}